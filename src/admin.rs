@@ -0,0 +1,226 @@
+use std::time::{Duration, Instant};
+
+use tiberius::Row;
+
+use crate::{ClientExt, Pool, SqlServerResult};
+
+/// Typed wrappers around well-known DMV queries for monitoring and admin tooling.
+///
+/// Most of these require the `VIEW SERVER STATE` permission.
+pub struct SystemQueries;
+
+/// A user session, see [`SystemQueries::active_sessions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    /// Session id (`@@SPID`).
+    pub session_id: i16,
+    /// Login name of the session.
+    pub login_name: String,
+    /// Client host name.
+    pub host_name: Option<String>,
+    /// Client program name.
+    pub program_name: Option<String>,
+    /// Current database of the session.
+    pub database_name: Option<String>,
+    /// Session status, e.g. `running` or `sleeping`.
+    pub status: String,
+    /// Command of the currently executing request, if any.
+    pub command: Option<String>,
+    /// Wait type of the currently executing request, if waiting.
+    pub wait_type: Option<String>,
+    /// Elapsed time of the currently executing request in milliseconds.
+    pub elapsed_ms: Option<i64>,
+}
+
+/// A session which is blocked or blocking others, see [`SystemQueries::blocking_tree`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockingEntry {
+    /// Session id.
+    pub session_id: i16,
+    /// Session id blocking this session, `None` for the head of a blocking chain.
+    pub blocked_by: Option<i16>,
+    /// Wait type of the session's request, `None` without request, e.g. for a sleeping head
+    /// holding locks in an open transaction.
+    pub wait_type: Option<String>,
+    /// Time waited in milliseconds, 0 without request.
+    pub wait_ms: i64,
+    /// Database the session runs in.
+    pub database_name: Option<String>,
+    /// Sql text of the session's request, or of its last request without one.
+    pub sql_text: Option<String>,
+}
+
+/// Allocated size of a database's files, see [`SystemQueries::database_size`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatabaseSize {
+    /// Size of the data files in bytes.
+    pub data_bytes: i64,
+    /// Size of the log files in bytes.
+    pub log_bytes: i64,
+}
+
+/// Result of [`self_test`], meant to be attached to bug reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SelfTestReport {
+    /// `@@VERSION` of the server.
+    pub server_version: String,
+    /// Whether the connection is encrypted.
+    pub encrypted: bool,
+    /// Current database of the connection.
+    pub database: String,
+    /// Login name of the connection.
+    pub login: String,
+    /// `@@TRANCOUNT` of the connection, anything but `0` hints at a leaked transaction.
+    pub trancount: i32,
+    /// Round trip time of a `SELECT 1`.
+    pub round_trip: Duration,
+    /// Error of creating, writing and dropping a temp table, `None` if it succeeded.
+    pub temp_table_error: Option<String>,
+}
+
+/// Run a battery of checks on one connection of `pool`, see [`SelfTestReport`].
+pub async fn self_test(pool: &Pool) -> SqlServerResult<SelfTestReport> {
+    let mut conn = pool.get().await?;
+
+    let started = Instant::now();
+    conn.simple_query("SELECT 1").await?.into_row().await?;
+    let round_trip = started.elapsed();
+
+    let row = conn
+        .simple_query("SELECT @@VERSION, DB_NAME(), SUSER_SNAME(), @@TRANCOUNT")
+        .await?
+        .into_row()
+        .await?;
+    let (server_version, database, login, trancount) = match &row {
+        Some(row) => (
+            owned(row, 0)?.unwrap_or_default(),
+            owned(row, 1)?.unwrap_or_default(),
+            owned(row, 2)?.unwrap_or_default(),
+            row.try_get(3)?.unwrap_or_default(),
+        ),
+        None => Default::default(),
+    };
+    let encrypted = conn.is_encrypted().await?;
+    let temp_table_error = conn
+        .execute(
+            "CREATE TABLE #deadpool_self_test (id int); \
+             INSERT INTO #deadpool_self_test VALUES (1); \
+             DROP TABLE #deadpool_self_test;",
+            &[],
+        )
+        .await
+        .err()
+        .map(|e| e.to_string());
+
+    Ok(SelfTestReport {
+        server_version,
+        encrypted,
+        database,
+        login,
+        trancount,
+        round_trip,
+        temp_table_error,
+    })
+}
+
+impl SystemQueries {
+    /// All user sessions along with their currently executing request.
+    pub async fn active_sessions(pool: &Pool) -> SqlServerResult<Vec<Session>> {
+        let mut conn = pool.get().await?;
+        let rows = conn
+            .simple_query(
+                "SELECT s.session_id, s.login_name, s.host_name, s.program_name, \
+                        DB_NAME(s.database_id), s.status, r.command, r.wait_type, \
+                        CAST(r.total_elapsed_time AS bigint) \
+                 FROM sys.dm_exec_sessions s \
+                 LEFT JOIN sys.dm_exec_requests r ON r.session_id = s.session_id \
+                 WHERE s.is_user_process = 1",
+            )
+            .await?
+            .into_first_result()
+            .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(Session {
+                    session_id: row.try_get(0)?.unwrap_or_default(),
+                    login_name: owned(row, 1)?.unwrap_or_default(),
+                    host_name: owned(row, 2)?,
+                    program_name: owned(row, 3)?,
+                    database_name: owned(row, 4)?,
+                    status: owned(row, 5)?.unwrap_or_default(),
+                    command: owned(row, 6)?,
+                    wait_type: owned(row, 7)?,
+                    elapsed_ms: row.try_get(8)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Sessions taking part in blocking, as a flat list of edges: each entry points to the
+    /// session blocking it, heads of the chains have no `blocked_by`.
+    ///
+    /// Heads are often sleeping sessions holding locks in an open transaction, they are listed
+    /// with the text of their last request.
+    pub async fn blocking_tree(pool: &Pool) -> SqlServerResult<Vec<BlockingEntry>> {
+        let mut conn = pool.get().await?;
+        let rows = conn
+            .simple_query(
+                "SELECT s.session_id, NULLIF(r.blocking_session_id, 0), r.wait_type, \
+                        CAST(ISNULL(r.wait_time, 0) AS bigint), \
+                        DB_NAME(ISNULL(r.database_id, s.database_id)), t.text \
+                 FROM sys.dm_exec_sessions s \
+                 LEFT JOIN sys.dm_exec_requests r ON r.session_id = s.session_id \
+                 LEFT JOIN sys.dm_exec_connections c \
+                    ON c.session_id = s.session_id AND c.parent_connection_id IS NULL \
+                 OUTER APPLY sys.dm_exec_sql_text(ISNULL(r.sql_handle, c.most_recent_sql_handle)) t \
+                 WHERE r.blocking_session_id <> 0 \
+                    OR s.session_id IN (SELECT blocking_session_id FROM sys.dm_exec_requests)",
+            )
+            .await?
+            .into_first_result()
+            .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(BlockingEntry {
+                    session_id: row.try_get(0)?.unwrap_or_default(),
+                    blocked_by: row.try_get(1)?,
+                    wait_type: owned(row, 2)?,
+                    wait_ms: row.try_get(3)?.unwrap_or_default(),
+                    database_name: owned(row, 4)?,
+                    sql_text: owned(row, 5)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Allocated size of `database`, `None` if there is no such database.
+    pub async fn database_size(
+        pool: &Pool,
+        database: &str,
+    ) -> SqlServerResult<Option<DatabaseSize>> {
+        let mut conn = pool.get().await?;
+        let row = conn
+            .query(
+                "SELECT CAST(SUM(CASE WHEN type_desc = 'ROWS' THEN size ELSE 0 END) AS bigint) * 8192, \
+                        CAST(SUM(CASE WHEN type_desc = 'LOG' THEN size ELSE 0 END) AS bigint) * 8192 \
+                 FROM sys.master_files WHERE database_id = DB_ID(@P1) \
+                 HAVING COUNT(*) > 0",
+                &[&database],
+            )
+            .await?
+            .into_row()
+            .await?;
+        row.map(|row| {
+            Ok(DatabaseSize {
+                data_bytes: row.try_get(0)?.unwrap_or_default(),
+                log_bytes: row.try_get(1)?.unwrap_or_default(),
+            })
+        })
+        .transpose()
+    }
+}
+
+fn owned(row: &Row, idx: usize) -> SqlServerResult<Option<String>> {
+    Ok(row.try_get::<&str, _>(idx)?.map(str::to_string))
+}
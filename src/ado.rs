@@ -0,0 +1,112 @@
+use tiberius::EncryptionLevel;
+
+use crate::Manager;
+
+impl Manager {
+    /// The configured connection as an ADO.NET connection string, e.g. to hand it to tools
+    /// outside Rust, see [`to_ado_string_redacted`](Manager::to_ado_string_redacted) for logging.
+    ///
+    /// Covers the server, instance and port, database, credentials, application name,
+    /// encryption, certificate trust, application intent and `MultiSubnetFailover`, the pool
+    /// settings have no connection string equivalent. Parsing the result with
+    /// [`from_ado_string`](Manager::from_ado_string) yields the same connection config.
+    ///
+    /// The user and password are only known when set with [`basic_authentication`] or a
+    /// connection string, they are left out otherwise. AAD tokens are never written, and
+    /// [`EncryptionLevel::On`] is written as `Encrypt=true`, as connection strings can't express
+    /// it. Settings of a config passed to [`from_config`](Manager::from_config) are unknown, only
+    /// those made on the manager afterwards are written.
+    ///
+    /// [`basic_authentication`]: Manager::basic_authentication
+    pub fn to_ado_string(&self) -> String {
+        self.ado_string(false)
+    }
+
+    /// [`to_ado_string`](Manager::to_ado_string) with the password replaced by `<HIDDEN>`, safe
+    /// to log.
+    pub fn to_ado_string_redacted(&self) -> String {
+        self.ado_string(true)
+    }
+
+    fn ado_string(&self, redact: bool) -> String {
+        let mut pairs = Vec::new();
+        if self.host.is_some() || self.instance_name.is_some() || self.port.is_some() {
+            let mut server = format!("tcp:{}", self.host.as_deref().unwrap_or("localhost"));
+            if let Some(instance) = &self.instance_name {
+                server = format!("{}\\{}", server, instance);
+            }
+            if let Some(port) = self.port {
+                server = format!("{},{}", server, port);
+            }
+            pairs.push(("Server", server));
+        }
+        if let Some(database) = &self.database {
+            pairs.push(("Database", database.clone()));
+        }
+
+        if self.integrated_security {
+            pairs.push(("Integrated Security", "true".to_string()));
+        }
+        if let Some(user) = &self.user {
+            pairs.push(("User ID", user.clone()));
+            match &self.password {
+                Some(_) if redact => pairs.push(("Password", "<HIDDEN>".to_string())),
+                Some(password) => pairs.push(("Password", password.clone())),
+                None => {}
+            }
+        }
+
+        if let Some(name) = &self.application_name {
+            pairs.push(("Application Name", name.clone()));
+        }
+        let encrypt = match self.encryption {
+            EncryptionLevel::Off => "false",
+            EncryptionLevel::NotSupported => "DANGER_PLAINTEXT",
+            _ => "true",
+        };
+        pairs.push(("Encrypt", encrypt.to_string()));
+        if self.trust_cert {
+            pairs.push(("TrustServerCertificate", "true".to_string()));
+        } else if let Some(path) = &self.trust_cert_ca {
+            pairs.push((
+                "TrustServerCertificateCA",
+                path.to_string_lossy().into_owned(),
+            ));
+        }
+        if self.readonly {
+            pairs.push(("ApplicationIntent", "ReadOnly".to_string()));
+        }
+        if self.multi_subnet_failover {
+            pairs.push(("MultiSubnetFailover", "true".to_string()));
+        }
+
+        pairs
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, escape_value(value)))
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+}
+
+/// Escape the characters delimiting connection string values, others are kept verbatim.
+///
+/// Quoted sections can't follow each other, the parser reads `'a''b'` as one section holding
+/// `a''b`, so quotes only wrap runs of `}`, which braces can't hold.
+fn escape_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' | '=' | '{' | ' ' | '\n' | '"' | '\'' => escaped.extend(['{', c, '}']),
+            '}' => {
+                escaped.push_str("\"}");
+                while chars.next_if_eq(&'}').is_some() {
+                    escaped.push('}');
+                }
+                escaped.push('"');
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
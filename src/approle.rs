@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use deadpool::managed::{Hook, HookError, Metrics};
+
+use crate::{Client, Manager, SqlServerResult};
+
+/// Session context key under which the cookie of the active application role is kept.
+const COOKIE_KEY: &str = "N'deadpool_tiberius.approle_cookie'";
+
+/// An application role activated on every connection,
+/// see [`Manager::application_role`](crate::Manager::application_role).
+#[derive(Clone)]
+pub(crate) struct ApplicationRole {
+    name: String,
+    password: String,
+}
+
+impl ApplicationRole {
+    pub(crate) fn new(name: String, password: String) -> Self {
+        Self { name, password }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn activate_hook(self: &Arc<Self>) -> Hook<Manager> {
+        let role = self.clone();
+        Hook::async_fn(move |client: &mut Client, _: &Metrics| {
+            let role = role.clone();
+            Box::pin(async move { role.activate(client).await.map_err(HookError::Backend) })
+        })
+    }
+
+    pub(crate) fn deactivate_hook() -> Hook<Manager> {
+        Hook::async_fn(|client: &mut Client, _: &Metrics| {
+            Box::pin(async move { deactivate(client).await.map_err(HookError::Backend) })
+        })
+    }
+
+    /// `sp_setapprole` must run ad hoc rather than through `sp_executesql`, hence literals instead
+    /// of parameters. The cookie for deactivating is stashed in the session context.
+    async fn activate(&self, client: &mut Client) -> SqlServerResult<()> {
+        let sql = format!(
+            "DECLARE @cookie varbinary(8000); \
+             EXEC sp_setapprole @rolename = {}, @password = {}, \
+                  @fCreateCookie = true, @cookie = @cookie OUTPUT; \
+             EXEC sp_set_session_context @key = {}, @value = @cookie;",
+            quote_literal(&self.name),
+            quote_literal(&self.password),
+            COOKIE_KEY,
+        );
+        client.simple_query(sql).await?.into_results().await?;
+        Ok(())
+    }
+}
+
+async fn deactivate(client: &mut Client) -> SqlServerResult<()> {
+    let sql = format!(
+        "DECLARE @cookie varbinary(8000) = CAST(SESSION_CONTEXT({key}) AS varbinary(8000)); \
+         EXEC sp_unsetapprole @cookie; \
+         EXEC sp_set_session_context @key = {key}, @value = NULL;",
+        key = COOKIE_KEY,
+    );
+    client.simple_query(sql).await?.into_results().await?;
+    Ok(())
+}
+
+/// Quote `value` as an nvarchar literal, escaping `'`.
+fn quote_literal(value: &str) -> String {
+    format!("N'{}'", value.replace('\'', "''"))
+}
@@ -0,0 +1,70 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Fails connection creation fast after repeated failures, see
+/// [`Manager::circuit_breaker`](crate::Manager::circuit_breaker).
+pub(crate) struct CircuitBreaker {
+    threshold: u32,
+    window: Duration,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Consecutive failures since `since`.
+    failures: u32,
+    since: Option<Instant>,
+    /// When the breaker opened, or last let a probe through.
+    opened_at: Option<Instant>,
+}
+
+/// Clones the settings only, starting closed.
+impl Clone for CircuitBreaker {
+    fn clone(&self) -> Self {
+        Self::new(self.threshold, self.window, self.cooldown)
+    }
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(threshold: u32, window: Duration, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            window,
+            cooldown,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// The remaining cooldown while open. Once it has passed, a single caller is let through
+    /// to probe the server, the others keep failing for another cooldown.
+    pub(crate) fn check(&self) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        match state.opened_at {
+            Some(at) if at.elapsed() < self.cooldown => Err(self.cooldown - at.elapsed()),
+            Some(_) => {
+                state.opened_at = Some(Instant::now());
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn record(&self, success: bool) {
+        let mut state = self.state.lock().unwrap();
+        if success {
+            *state = State::default();
+            return;
+        }
+        match state.since {
+            Some(since) if since.elapsed() <= self.window => state.failures += 1,
+            _ => {
+                state.failures = 1;
+                state.since = Some(Instant::now());
+            }
+        }
+        if state.opened_at.is_some() || state.failures >= self.threshold {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
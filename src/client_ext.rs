@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use deadpool::async_trait;
+use deadpool::managed::Object;
+use tiberius::{QueryStream, ToSql};
+
+use crate::named::bind_named;
+use crate::taint::{Taint, Taints};
+use crate::{
+    comment_sql, quote_identifier, Client, Manager, ServerEdition, SqlServerError, SqlServerResult,
+    TransactionGuard,
+};
+
+/// Convenience methods on a (pooled) [`Client`].
+///
+/// Called on a pooled connection rather than on the [`Client`] it derefs to, [`use_database`]
+/// and a dropped [`begin_transaction`] guard mark the connection for a full recycle, see
+/// [`Manager::recycle_min_idle`](crate::Manager::recycle_min_idle).
+///
+/// [`use_database`]: ClientExt::use_database
+/// [`begin_transaction`]: ClientExt::begin_transaction
+#[async_trait]
+pub trait ClientExt {
+    /// Whether the transport of this connection is encrypted, as reported by the server
+    /// through `CONNECTIONPROPERTY('encrypt_option')`.
+    async fn is_encrypted(&mut self) -> SqlServerResult<bool>;
+
+    /// The engine edition of the server, from `SERVERPROPERTY('EngineEdition')`.
+    async fn server_edition(&mut self) -> SqlServerResult<ServerEdition>;
+
+    /// Switch the current database with `USE [name]`, `name` is bracket-quoted.
+    ///
+    /// On Azure SQL Database, which doesn't support `USE`, this fails with
+    /// [`SqlServerError::Unsupported`] instead of the server's error 40508.
+    async fn use_database(&mut self, name: &str) -> SqlServerResult<()>;
+
+    /// Execute `sql` with named `@name` placeholders bound from `params`.
+    ///
+    /// Placeholders are rewritten into tiberius' positional `@P1`, `@P2`... before executing,
+    /// a name used several times is bound once. Placeholders within string literals, quoted
+    /// identifiers and comments, including nested block comments, are ignored, so are `@@`
+    /// system functions and local variables declared in `sql` with `DECLARE`. Any other
+    /// `@name` must be present in `params`, otherwise
+    /// [`SqlServerError::MissingParameter`](crate::SqlServerError::MissingParameter) is returned.
+    /// ```no_run
+    /// # use std::collections::HashMap;
+    /// # use deadpool_tiberius::ClientExt;
+    /// # async fn f(client: &mut deadpool_tiberius::Client) -> deadpool_tiberius::SqlServerResult<()> {
+    /// let user_id = 42;
+    /// let mut params: HashMap<&str, &dyn tiberius::ToSql> = HashMap::new();
+    /// params.insert("userId", &user_id);
+    /// let rows = client
+    ///     .query_named("SELECT * FROM users WHERE id = @userId OR manager = @userId", &params)
+    ///     .await?
+    ///     .into_first_result()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn query_named<'a>(
+        &'a mut self,
+        sql: &str,
+        params: &HashMap<&str, &dyn ToSql>,
+    ) -> SqlServerResult<QueryStream<'a>>;
+
+    /// Execute `sql` prefixed with `comment` as a `/* */` block comment, attributing the query in
+    /// Query Store. Comment delimiters within `comment` are defused, so it can't inject sql.
+    async fn query_commented<'a>(
+        &'a mut self,
+        comment: &str,
+        sql: &str,
+        params: &[&dyn ToSql],
+    ) -> SqlServerResult<QueryStream<'a>>;
+
+    /// Start a transaction with `BEGIN TRANSACTION`, to be finished through the returned
+    /// [`TransactionGuard`].
+    /// ```no_run
+    /// # use deadpool_tiberius::ClientExt;
+    /// # async fn f(client: &mut deadpool_tiberius::Client) -> deadpool_tiberius::SqlServerResult<()> {
+    /// let mut tx = client.begin_transaction().await?;
+    /// tx.execute("UPDATE accounts SET balance = balance - 10 WHERE id = 1", &[]).await?;
+    /// tx.execute("UPDATE accounts SET balance = balance + 10 WHERE id = 2", &[]).await?;
+    /// tx.commit().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn begin_transaction(&mut self) -> SqlServerResult<TransactionGuard<'_>>;
+}
+
+#[async_trait]
+impl ClientExt for Client {
+    async fn is_encrypted(&mut self) -> SqlServerResult<bool> {
+        let row = self
+            .simple_query("SELECT CONVERT(nvarchar(10), CONNECTIONPROPERTY('encrypt_option'))")
+            .await?
+            .into_row()
+            .await?;
+        Ok(row
+            .and_then(|row| {
+                row.get::<&str, _>(0)
+                    .map(|v| v.eq_ignore_ascii_case("TRUE"))
+            })
+            .unwrap_or(false))
+    }
+
+    async fn query_named<'a>(
+        &'a mut self,
+        sql: &str,
+        params: &HashMap<&str, &dyn ToSql>,
+    ) -> SqlServerResult<QueryStream<'a>> {
+        let (sql, bound) = bind_named(sql, params)?;
+        Ok(self.query(sql, &bound).await?)
+    }
+
+    async fn server_edition(&mut self) -> SqlServerResult<ServerEdition> {
+        let row = self
+            .simple_query("SELECT CAST(SERVERPROPERTY('EngineEdition') AS int)")
+            .await?
+            .into_row()
+            .await?;
+        let value = match row {
+            Some(row) => row.try_get::<i32, _>(0)?.unwrap_or_default(),
+            None => 0,
+        };
+        Ok(ServerEdition::from_engine_edition(value))
+    }
+
+    async fn use_database(&mut self, name: &str) -> SqlServerResult<()> {
+        let result = match self
+            .simple_query(format!("USE {}", quote_identifier(name)))
+            .await
+        {
+            Ok(stream) => stream.into_results().await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        match result {
+            Err(e) if e.code() == Some(40508) => Err(SqlServerError::Unsupported(
+                "USE is not supported on Azure SQL Database, connect to the database instead",
+            )),
+            result => Ok(result?),
+        }
+    }
+
+    async fn query_commented<'a>(
+        &'a mut self,
+        comment: &str,
+        sql: &str,
+        params: &[&dyn ToSql],
+    ) -> SqlServerResult<QueryStream<'a>> {
+        Ok(self.query(comment_sql(comment, sql), params).await?)
+    }
+
+    async fn begin_transaction(&mut self) -> SqlServerResult<TransactionGuard<'_>> {
+        TransactionGuard::begin(self).await
+    }
+}
+
+#[async_trait]
+impl ClientExt for Object<Manager> {
+    async fn is_encrypted(&mut self) -> SqlServerResult<bool> {
+        (**self).is_encrypted().await
+    }
+
+    async fn server_edition(&mut self) -> SqlServerResult<ServerEdition> {
+        (**self).server_edition().await
+    }
+
+    async fn use_database(&mut self, name: &str) -> SqlServerResult<()> {
+        Taints::mark_pooled(self, Taint::Database);
+        (**self).use_database(name).await
+    }
+
+    async fn query_named<'a>(
+        &'a mut self,
+        sql: &str,
+        params: &HashMap<&str, &dyn ToSql>,
+    ) -> SqlServerResult<QueryStream<'a>> {
+        (**self).query_named(sql, params).await
+    }
+
+    async fn query_commented<'a>(
+        &'a mut self,
+        comment: &str,
+        sql: &str,
+        params: &[&dyn ToSql],
+    ) -> SqlServerResult<QueryStream<'a>> {
+        (**self).query_commented(comment, sql, params).await
+    }
+
+    async fn begin_transaction(&mut self) -> SqlServerResult<TransactionGuard<'_>> {
+        TransactionGuard::begin_pooled(self).await
+    }
+}
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use crate::{env_var, Manager};
+
+/// Pool configuration as found in config files, turned into a [`Manager`] with
+/// [`Manager::from_config_struct`].
+///
+/// All fields are optional, unset ones keep the defaults of [`Manager::new`].
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let config: deadpool_tiberius::ManagerConfig = serde_json::from_str(
+///     r#"{ "host": "db.example.com", "database": "app", "max_size": 20, "wait_timeout_ms": 500 }"#,
+/// )?;
+/// let manager = deadpool_tiberius::Manager::from_config_struct(config);
+/// # Ok(())
+/// # }
+/// ```
+#[non_exhaustive]
+#[derive(Clone, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ManagerConfig {
+    /// See [`Manager::host`].
+    pub host: Option<String>,
+    /// See [`Manager::port`].
+    pub port: Option<u16>,
+    /// See [`Manager::database`].
+    pub database: Option<String>,
+    /// Login for [`Manager::basic_authentication`], `MSSQL_USER` if unset.
+    pub username: Option<String>,
+    /// Password for [`Manager::basic_authentication`], `MSSQL_PASSWORD` if unset.
+    pub password: Option<String>,
+    /// See [`Manager::max_size`].
+    pub max_size: Option<usize>,
+    /// [`Manager::wait_timeout`] in milliseconds.
+    pub wait_timeout_ms: Option<u64>,
+    /// [`Manager::create_timeout`] in milliseconds.
+    pub create_timeout_ms: Option<u64>,
+    /// [`Manager::recycle_timeout`] in milliseconds.
+    pub recycle_timeout_ms: Option<u64>,
+}
+
+impl std::fmt::Debug for ManagerConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ManagerConfig")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("database", &self.database)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<HIDDEN>"))
+            .field("max_size", &self.max_size)
+            .field("wait_timeout_ms", &self.wait_timeout_ms)
+            .field("create_timeout_ms", &self.create_timeout_ms)
+            .field("recycle_timeout_ms", &self.recycle_timeout_ms)
+            .finish()
+    }
+}
+
+impl Manager {
+    /// Create a new ConnectionPool Manager from a [`ManagerConfig`] loaded from a config file.
+    ///
+    /// `username` and `password` fall back to the `MSSQL_USER` and `MSSQL_PASSWORD` environment
+    /// variables, so secrets can stay out of the file. Without either, no authentication is set
+    /// and [`create_pool`](Manager::create_pool) fails.
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub fn from_config_struct(config: ManagerConfig) -> Self {
+        let mut manager = Self::new();
+        if let Some(host) = config.host {
+            manager = manager.host(host);
+        }
+        if let Some(port) = config.port {
+            manager = manager.port(port);
+        }
+        if let Some(database) = config.database {
+            manager = manager.database(database);
+        }
+        let username = config.username.or_else(|| env_var("MSSQL_USER"));
+        let password = config.password.or_else(|| env_var("MSSQL_PASSWORD"));
+        if let (Some(username), Some(password)) = (username, password) {
+            manager = manager.basic_authentication(username, password);
+        }
+        if let Some(max_size) = config.max_size {
+            manager = manager.max_size(max_size);
+        }
+        if let Some(ms) = config.wait_timeout_ms {
+            manager = manager.wait_timeout(Duration::from_millis(ms));
+        }
+        if let Some(ms) = config.create_timeout_ms {
+            manager = manager.create_timeout(Duration::from_millis(ms));
+        }
+        if let Some(ms) = config.recycle_timeout_ms {
+            manager = manager.recycle_timeout(Duration::from_millis(ms));
+        }
+        manager
+    }
+}
+
+impl From<ManagerConfig> for Manager {
+    fn from(config: ManagerConfig) -> Self {
+        Self::from_config_struct(config)
+    }
+}
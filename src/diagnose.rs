@@ -0,0 +1,61 @@
+use tiberius::error::Error;
+
+use crate::SqlServerError;
+
+/// Runs a quick diagnostic sequence against `addr` after connection creation failed,
+/// wrapping `err` with a description of the stage which failed.
+pub(crate) async fn diagnose(addr: &str, err: SqlServerError) -> SqlServerError {
+    let diagnosis = match tokio::net::lookup_host(addr).await {
+        Err(e) => format!("DNS resolution of `{}` failed ({})", addr, e),
+        Ok(addrs) => {
+            let addrs: Vec<_> = addrs.collect();
+            let mut last_err = None;
+            let mut reachable = false;
+            for socket_addr in &addrs {
+                match tokio::net::TcpStream::connect(socket_addr).await {
+                    Ok(_) => {
+                        reachable = true;
+                        break;
+                    }
+                    Err(e) => last_err = Some((socket_addr, e)),
+                }
+            }
+            match (reachable, last_err) {
+                (true, _) => describe_after_tcp(addr, &err),
+                (false, Some((socket_addr, e))) => {
+                    format!("TCP connect to `{}` failed ({})", socket_addr, e)
+                }
+                (false, None) => format!("DNS resolution of `{}` returned no address", addr),
+            }
+        }
+    };
+    SqlServerError::Diagnosed {
+        diagnosis,
+        source: Box::new(err),
+    }
+}
+
+fn describe_after_tcp(addr: &str, err: &SqlServerError) -> String {
+    let err = match err {
+        SqlServerError::ConnectFailed { source, .. } => source,
+        err => err,
+    };
+    match err {
+        SqlServerError::Tiberius(Error::Tls(_)) => {
+            format!("TCP connect to `{}` succeeded, TLS handshake failed", addr)
+        }
+        SqlServerError::LoginFailed { .. } => {
+            format!("TCP connect to `{}` succeeded, login failed", addr)
+        }
+        SqlServerError::Tiberius(Error::Server(e)) if e.code() == 4060 => {
+            format!(
+                "TCP connect to `{}` succeeded, database could not be opened",
+                addr
+            )
+        }
+        _ => format!(
+            "TCP connect to `{}` succeeded, failed during TLS/login/initialization",
+            addr
+        ),
+    }
+}
@@ -0,0 +1,64 @@
+/// Engine edition of the server, from `SERVERPROPERTY('EngineEdition')`.
+///
+/// See [`Manager::detect_server_edition`](crate::Manager::detect_server_edition).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerEdition {
+    /// Personal or Desktop Engine.
+    Personal,
+    /// Standard, Web or Business Intelligence.
+    Standard,
+    /// Enterprise, Developer or Evaluation.
+    Enterprise,
+    /// Express.
+    Express,
+    /// Azure SQL Database.
+    AzureSqlDatabase,
+    /// Azure Synapse Analytics dedicated pool.
+    AzureSynapse,
+    /// Azure SQL Managed Instance.
+    AzureSqlManagedInstance,
+    /// Azure SQL Edge.
+    AzureSqlEdge,
+    /// Azure Synapse serverless pool.
+    AzureSynapseServerless,
+    /// Any edition unknown to this crate.
+    Other(i32),
+}
+
+impl ServerEdition {
+    pub(crate) fn from_engine_edition(value: i32) -> Self {
+        match value {
+            1 => Self::Personal,
+            2 => Self::Standard,
+            3 => Self::Enterprise,
+            4 => Self::Express,
+            5 => Self::AzureSqlDatabase,
+            6 => Self::AzureSynapse,
+            8 => Self::AzureSqlManagedInstance,
+            9 => Self::AzureSqlEdge,
+            11 => Self::AzureSynapseServerless,
+            v => Self::Other(v),
+        }
+    }
+
+    /// Whether the edition runs in Azure.
+    pub fn is_azure(&self) -> bool {
+        matches!(
+            self,
+            Self::AzureSqlDatabase
+                | Self::AzureSynapse
+                | Self::AzureSqlManagedInstance
+                | Self::AzureSqlEdge
+                | Self::AzureSynapseServerless
+        )
+    }
+
+    /// Whether `USE` can switch the database of a connection, which Azure SQL Database and
+    /// Synapse don't support.
+    pub fn supports_use(&self) -> bool {
+        !matches!(
+            self,
+            Self::AzureSqlDatabase | Self::AzureSynapse | Self::AzureSynapseServerless
+        )
+    }
+}
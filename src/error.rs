@@ -1,4 +1,4 @@
-use deadpool::managed::{BuildError, PoolError};
+use deadpool::managed::{BuildError, PoolError, TimeoutType};
 
 /// Type aliasing for Result<T, [`SqlServerError`]>
 pub type SqlServerResult<T> = Result<T, SqlServerError>;
@@ -8,14 +8,347 @@ pub type SqlServerResult<T> = Result<T, SqlServerError>;
 pub enum SqlServerError {
     /// Error caused by tiberius execution.
     #[error(transparent)]
-    Tiberius(#[from] tiberius::error::Error),
+    Tiberius(tiberius::error::Error),
     /// Error caused by io.
     #[error(transparent)]
     Io(#[from] std::io::Error),
     /// Error from [`PoolError`].
     #[error(transparent)]
-    Pool(#[from] PoolError<tiberius::error::Error>),
+    Pool(Box<PoolError<SqlServerError>>),
+    /// An operation timed out, e.g. waiting for, creating or recycling a pool connection, or a
+    /// query. `kind` tells which one.
+    #[error("{kind} timed out")]
+    Timeout {
+        /// The operation which timed out.
+        kind: TimeoutKind,
+    },
     /// Error from when building pool.
     #[error(transparent)]
     PoolBuild(#[from] BuildError),
-}
\ No newline at end of file
+    /// Connection creation failed, enriched with the result of the diagnostic sequence.
+    /// See [`Manager::diagnose_on_failure`](crate::Manager::diagnose_on_failure).
+    #[error("{diagnosis}: {source}")]
+    Diagnosed {
+        /// Human-readable description of the stage which failed.
+        diagnosis: String,
+        /// The original error from connection creation.
+        source: Box<SqlServerError>,
+    },
+    /// The established connection is not encrypted though encryption was required.
+    /// See [`Manager::encryption_required`](crate::Manager::encryption_required).
+    #[error("connection is not encrypted though encryption is required")]
+    EncryptionDowngrade,
+    /// The requested configuration is not supported.
+    #[error("unsupported: {0}")]
+    Unsupported(&'static str),
+    /// Connection creation failed recently, the cached error message is returned without a new
+    /// attempt. See [`Manager::create_error_cache`](crate::Manager::create_error_cache).
+    #[error("connection creation failed recently: {0}")]
+    CreateErrorCached(String),
+    /// Connection creation failed repeatedly and is not attempted again until the cooldown has
+    /// passed. See [`Manager::circuit_breaker`](crate::Manager::circuit_breaker).
+    #[error("circuit breaker open, retrying in {retry_in:?}")]
+    CircuitOpen {
+        /// Time left until the server is probed again.
+        retry_in: std::time::Duration,
+    },
+    /// A named placeholder has no value provided.
+    #[error("no value provided for parameter `@{0}`")]
+    MissingParameter(String),
+    /// A required environment variable is not set, see
+    /// [`Manager::from_env`](crate::Manager::from_env).
+    #[error("environment variable `{0}` is not set")]
+    MissingEnvVar(&'static str),
+    /// The configuration is invalid, detected when building the pool.
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+    /// A hook panicked, caught as [`Manager::catch_hook_panics`](crate::Manager::catch_hook_panics)
+    /// is enabled.
+    #[error("hook panicked: {0}")]
+    HookPanicked(String),
+    /// Fetching an access token failed, see `Manager::aad_token_provider` of the `azure`
+    /// feature.
+    #[error("fetching AAD token failed: {0}")]
+    AadToken(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// Creating a connection failed, tagged with the phase which failed.
+    #[error("connecting failed during {phase}: {source}")]
+    ConnectFailed {
+        /// The phase which failed.
+        phase: ConnectPhase,
+        /// The error of the phase.
+        source: Box<SqlServerError>,
+    },
+    /// Resolving or connecting to `host` failed, the source of a
+    /// [`SqlServerError::ConnectFailed`] in the DNS and TCP phases.
+    #[error("`{host}`: {source}")]
+    Connection {
+        /// The address resolved or connected to, the resolved IP address once connecting.
+        host: String,
+        /// The error, e.g. an I/O error for a refused connection or an unknown host, or a
+        /// [`SqlServerError::Timeout`].
+        source: Box<SqlServerError>,
+    },
+    /// Login failed (sql server error 18456), with the cause derived from the error state.
+    ///
+    /// Note that servers usually withhold the precise state from clients and report state 1,
+    /// the actual state is then only found in the server's error log.
+    #[error("Login failed: {reason} (state {state})")]
+    LoginFailed {
+        /// State of the 18456 error.
+        state: u8,
+        /// Human-readable cause of the state.
+        reason: &'static str,
+        /// The original error.
+        source: tiberius::error::Error,
+    },
+    /// The server rejected the login for a reason other than [`SqlServerError::LoginFailed`],
+    /// e.g. a locked account or an expired password.
+    #[error("authentication failed: {source}")]
+    Authentication {
+        /// The sql server error number.
+        code: u32,
+        /// The original error.
+        source: tiberius::error::Error,
+    },
+    /// The value of a scalar query has another type than requested, see
+    /// [`PoolQueryExt::fetch_scalar`](crate::PoolQueryExt::fetch_scalar).
+    #[error("scalar value can't be read as `{target}`: {source}")]
+    ScalarConversion {
+        /// The requested type.
+        target: &'static str,
+        /// The conversion error.
+        source: tiberius::error::Error,
+    },
+    /// The servers kept redirecting the login, more than 5 times, e.g. as they are
+    /// misconfigured to redirect to each other.
+    #[error("too many routing redirects, the last one to `{host}:{port}`")]
+    TooManyRedirects {
+        /// The server last redirected to.
+        host: String,
+        /// Its port.
+        port: u16,
+    },
+    /// The server couldn't open the database to connect to (sql server error 4060, or 18456
+    /// state 38), as it doesn't exist or the login may not access it.
+    #[error("cannot open database `{database}`: {source}")]
+    DatabaseUnavailable {
+        /// The configured database, or the one named by the server.
+        database: String,
+        /// The original error.
+        source: Box<SqlServerError>,
+    },
+}
+
+/// Operation which timed out, see [`SqlServerError::Timeout`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutKind {
+    /// Waiting for a free slot in the pool,
+    /// see [`Manager::wait_timeout`](crate::Manager::wait_timeout).
+    Wait,
+    /// Creating a new connection,
+    /// see [`Manager::create_timeout`](crate::Manager::create_timeout).
+    Create,
+    /// Recycling an idle connection,
+    /// see [`Manager::recycle_timeout`](crate::Manager::recycle_timeout).
+    Recycle,
+    /// Establishing the TCP connection to an address,
+    /// see [`Manager::connect_timeout`](crate::Manager::connect_timeout).
+    Connect,
+    /// Retrieving a connection as a whole, see [`PoolExt::get_timeout`](crate::PoolExt::get_timeout).
+    Get,
+    /// A query run through [`PoolQueryExt`](crate::PoolQueryExt),
+    /// see [`Manager::query_timeout`](crate::Manager::query_timeout).
+    Query,
+}
+
+impl From<TimeoutType> for TimeoutKind {
+    fn from(value: TimeoutType) -> Self {
+        match value {
+            TimeoutType::Wait => TimeoutKind::Wait,
+            TimeoutType::Create => TimeoutKind::Create,
+            TimeoutType::Recycle => TimeoutKind::Recycle,
+        }
+    }
+}
+
+impl std::fmt::Display for TimeoutKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TimeoutKind::Wait => "connection wait",
+            TimeoutKind::Create => "connection create",
+            TimeoutKind::Recycle => "connection recycle",
+            TimeoutKind::Connect => "TCP connect",
+            TimeoutKind::Get => "connection get",
+            TimeoutKind::Query => "query",
+        })
+    }
+}
+
+/// Phase of creating a connection, see [`SqlServerError::ConnectFailed`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectPhase {
+    /// Resolving the host into addresses.
+    Dns,
+    /// Establishing the TCP connection, including sql browser lookups, or connecting the
+    /// Unix domain socket or named pipe.
+    Tcp,
+    /// The TLS handshake.
+    Tls,
+    /// The prelogin and login exchange with the server.
+    Login,
+    /// Initialization after login: encryption verification, edition detection and
+    /// [`Manager::on_create`](crate::Manager::on_create).
+    Init,
+}
+
+impl std::fmt::Display for ConnectPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ConnectPhase::Dns => "DNS resolution",
+            ConnectPhase::Tcp => "TCP connect",
+            ConnectPhase::Tls => "TLS handshake",
+            ConnectPhase::Login => "login",
+            ConnectPhase::Init => "initialization",
+        })
+    }
+}
+
+impl SqlServerError {
+    /// Whether the server rejected the credentials, see [`SqlServerError::LoginFailed`] and
+    /// [`SqlServerError::Authentication`], also when wrapped by [`SqlServerError::ConnectFailed`]
+    /// or [`SqlServerError::Diagnosed`].
+    pub fn is_authentication(&self) -> bool {
+        match self {
+            SqlServerError::LoginFailed { .. } | SqlServerError::Authentication { .. } => true,
+            SqlServerError::ConnectFailed { source, .. }
+            | SqlServerError::Diagnosed { source, .. } => source.is_authentication(),
+            _ => false,
+        }
+    }
+
+    /// Whether the server couldn't be reached or the connection broke: resolving or connecting
+    /// failed, an I/O error occurred or the circuit breaker is open. Looks through the same
+    /// wrappers as [`is_authentication`](SqlServerError::is_authentication).
+    ///
+    /// A failed TLS handshake, e.g. a rejected certificate, fails the same way every time and
+    /// only counts if it broke on an I/O error.
+    pub fn is_connection(&self) -> bool {
+        match self {
+            SqlServerError::ConnectFailed {
+                phase: ConnectPhase::Dns | ConnectPhase::Tcp,
+                ..
+            } => true,
+            SqlServerError::ConnectFailed { source, .. }
+            | SqlServerError::Diagnosed { source, .. } => source.is_connection(),
+            SqlServerError::Io(_) | SqlServerError::Tiberius(tiberius::error::Error::Io { .. }) => {
+                true
+            }
+            SqlServerError::Timeout {
+                kind: TimeoutKind::Connect,
+            }
+            | SqlServerError::Connection { .. }
+            | SqlServerError::CircuitOpen { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Tag errors rejecting the `database` connected to as
+    /// [`SqlServerError::DatabaseUnavailable`], others are returned unchanged.
+    pub(crate) fn database_unavailable(self, database: Option<String>) -> Self {
+        let Some(e) = self.token_error() else {
+            return self;
+        };
+        if e.code() != 4060 && (e.code() != 18456 || e.state() != 38) {
+            return self;
+        }
+        // 4060 names the database: Cannot open database "name" requested by the login.
+        let named = e.message().split('"').nth(1).map(str::to_string);
+        let database = database
+            .or(named)
+            .unwrap_or_else(|| "<default>".to_string());
+        SqlServerError::DatabaseUnavailable {
+            database,
+            source: Box::new(self),
+        }
+    }
+
+    fn token_error(&self) -> Option<&tiberius::error::TokenError> {
+        match self {
+            SqlServerError::Tiberius(tiberius::error::Error::Server(e))
+            | SqlServerError::LoginFailed {
+                source: tiberius::error::Error::Server(e),
+                ..
+            } => Some(e),
+            SqlServerError::ConnectFailed { source, .. }
+            | SqlServerError::Diagnosed { source, .. } => source.token_error(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn connection(host: &str, source: impl Into<SqlServerError>) -> Self {
+        SqlServerError::Connection {
+            host: host.to_string(),
+            source: Box::new(source.into()),
+        }
+    }
+
+    pub(crate) fn connect_failed(phase: ConnectPhase, source: impl Into<SqlServerError>) -> Self {
+        SqlServerError::ConnectFailed {
+            phase,
+            source: Box::new(source.into()),
+        }
+    }
+}
+
+impl From<tiberius::error::Error> for SqlServerError {
+    fn from(value: tiberius::error::Error) -> Self {
+        match &value {
+            tiberius::error::Error::Server(e) if e.code() == 18456 => SqlServerError::LoginFailed {
+                state: e.state(),
+                reason: login_failed_reason(e.state()),
+                source: value,
+            },
+            tiberius::error::Error::Server(e) if AUTHENTICATION_ERRORS.contains(&e.code()) => {
+                SqlServerError::Authentication {
+                    code: e.code(),
+                    source: value,
+                }
+            }
+            _ => SqlServerError::Tiberius(value),
+        }
+    }
+}
+
+impl From<PoolError<SqlServerError>> for SqlServerError {
+    fn from(value: PoolError<SqlServerError>) -> Self {
+        match value {
+            PoolError::Backend(e) => e,
+            PoolError::Timeout(kind) => SqlServerError::Timeout { kind: kind.into() },
+            e => SqlServerError::Pool(Box::new(e)),
+        }
+    }
+}
+
+/// Server errors rejecting a login besides 18456: untrusted domain, account disabled, locked
+/// out, password expired and password must be changed.
+const AUTHENTICATION_ERRORS: [u32; 5] = [18452, 18470, 18486, 18487, 18488];
+
+fn login_failed_reason(state: u8) -> &'static str {
+    match state {
+        1 => "details withheld by the server, see its error log",
+        2 | 5 => "login does not exist",
+        6 => "windows login used with sql server authentication",
+        7 => "login disabled and password incorrect",
+        8 | 9 => "password incorrect",
+        11 | 12 => "login valid but server access denied",
+        13 => "server is paused",
+        18 => "password must be changed",
+        38 | 40 => "default database inaccessible",
+        46 => "default database inaccessible, login may have been renamed",
+        58 => "sql server authentication not enabled on the server",
+        102..=111 => "azure active directory authentication failed",
+        _ => "unknown reason",
+    }
+}
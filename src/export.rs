@@ -0,0 +1,244 @@
+use futures_util::TryStreamExt;
+use tiberius::{ColumnData, QueryItem};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{Pool, SqlServerResult};
+
+/// Output format of [`export_query`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Comma separated values with a header line, `NULL` is written as an empty field.
+    Csv,
+}
+
+/// Stream the first result set of `sql` into `writer`, returns the number of rows written.
+///
+/// Rows are serialized one at a time while reading from the server, so the result set is never
+/// materialized in memory. The connection is returned to the pool when done.
+///
+/// Values are encoded as follows in [`ExportFormat::Csv`]:
+/// - `datetime`/`datetime2`/`date`/`time` in ISO-8601 (`2023-01-31 12:00:00.000`).
+/// - `decimal`/`numeric` with their full scale, `uniqueidentifier` in hyphenated form.
+/// - `binary`/`varbinary` as hex prefixed with `0x`, `bit` as `1`/`0`.
+///
+/// # Example
+/// ```no_run
+/// # async fn f(pool: deadpool_tiberius::Pool) -> deadpool_tiberius::SqlServerResult<()> {
+/// let mut file = tokio::fs::File::create("out.csv").await?;
+/// let rows = deadpool_tiberius::export_query(
+///     &pool,
+///     "SELECT * FROM my_table",
+///     &mut file,
+///     deadpool_tiberius::ExportFormat::Csv,
+/// )
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn export_query<W>(
+    pool: &Pool,
+    sql: &str,
+    writer: &mut W,
+    format: ExportFormat,
+) -> SqlServerResult<u64>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut conn = pool.get().await?;
+    let mut stream = conn.simple_query(sql).await?;
+    let mut rows = 0;
+    let mut line = String::new();
+
+    while let Some(item) = stream.try_next().await? {
+        line.clear();
+        match item {
+            QueryItem::Metadata(meta) if meta.result_index() == 0 => match format {
+                ExportFormat::Csv => {
+                    let names = meta.columns().iter().map(|c| escape_csv(c.name()));
+                    line.push_str(&names.collect::<Vec<_>>().join(","));
+                }
+            },
+            QueryItem::Row(row) if row.result_index() == 0 => match format {
+                ExportFormat::Csv => {
+                    let values = row.cells().map(|(_, data)| escape_csv(&format_csv(data)));
+                    line.push_str(&values.collect::<Vec<_>>().join(","));
+                    rows += 1;
+                }
+            },
+            _ => continue,
+        }
+        line.push_str("\r\n");
+        writer.write_all(line.as_bytes()).await?;
+    }
+    writer.flush().await?;
+
+    Ok(rows)
+}
+
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn format_csv(data: &ColumnData<'static>) -> String {
+    fn opt<T>(v: &Option<T>, f: impl FnOnce(&T) -> String) -> String {
+        v.as_ref().map(f).unwrap_or_default()
+    }
+
+    match data {
+        ColumnData::U8(v) => opt(v, u8::to_string),
+        ColumnData::I16(v) => opt(v, i16::to_string),
+        ColumnData::I32(v) => opt(v, i32::to_string),
+        ColumnData::I64(v) => opt(v, i64::to_string),
+        ColumnData::F32(v) => opt(v, f32::to_string),
+        ColumnData::F64(v) => opt(v, f64::to_string),
+        ColumnData::Bit(v) => opt(v, |v| if *v { "1" } else { "0" }.to_string()),
+        ColumnData::String(v) => opt(v, |v| v.to_string()),
+        ColumnData::Guid(v) => opt(v, |v| v.to_string()),
+        ColumnData::Binary(v) => opt(v, |v| {
+            let hex: String = v.iter().map(|b| format!("{:02X}", b)).collect();
+            format!("0x{}", hex)
+        }),
+        ColumnData::Numeric(v) => opt(v, |v| v.to_string()),
+        ColumnData::Xml(v) => opt(v, |v| v.to_string()),
+        ColumnData::DateTime(v) => opt(v, |v| {
+            // Ticks of 1/300 s, rounded to the millisecond like SQL Server displays them.
+            let millis = (v.seconds_fragments() as u64 * 10 + 1) / 3;
+            format_datetime(v.days() as i64 + DAYS_TO_1900, millis * 1_000_000, 3)
+        }),
+        ColumnData::SmallDateTime(v) => opt(v, |v| {
+            // Minutes since midnight.
+            let nanos = v.seconds_fragments() as u64 * 60 * 1_000_000_000;
+            format_datetime(v.days() as i64 + DAYS_TO_1900, nanos, 0)
+        }),
+        #[cfg(feature = "tds73")]
+        ColumnData::Time(v) => opt(v, |v| format_time(time_nanos(v), v.scale())),
+        #[cfg(feature = "tds73")]
+        ColumnData::Date(v) => opt(v, |v| format_date(v.days() as i64)),
+        #[cfg(feature = "tds73")]
+        ColumnData::DateTime2(v) => opt(v, |v| {
+            let time = v.time();
+            format_datetime(v.date().days() as i64, time_nanos(&time), time.scale())
+        }),
+        #[cfg(feature = "tds73")]
+        ColumnData::DateTimeOffset(v) => opt(v, |v| {
+            const NANOS_PER_DAY: i128 = 86_400_000_000_000;
+            let dt = v.datetime2();
+            let time = dt.time();
+            let utc = dt.date().days() as i128 * NANOS_PER_DAY + time_nanos(&time) as i128;
+            let local = utc + v.offset() as i128 * 60_000_000_000;
+            let offset = v.offset().unsigned_abs();
+            format!(
+                "{} {}{:02}:{:02}",
+                format_datetime(
+                    local.div_euclid(NANOS_PER_DAY) as i64,
+                    local.rem_euclid(NANOS_PER_DAY) as u64,
+                    time.scale()
+                ),
+                if v.offset() < 0 { '-' } else { '+' },
+                offset / 60,
+                offset % 60
+            )
+        }),
+        #[allow(unreachable_patterns)]
+        _ => String::new(),
+    }
+}
+
+/// Days from 0001-01-01 to 1900-01-01.
+const DAYS_TO_1900: i64 = 693_595;
+
+#[cfg(feature = "tds73")]
+fn time_nanos(time: &tiberius::time::Time) -> u64 {
+    time.increments() * 10u64.pow(9 - time.scale() as u32)
+}
+
+fn format_datetime(days: i64, nanos: u64, scale: u8) -> String {
+    format!("{} {}", format_date(days), format_time(nanos, scale))
+}
+
+/// Format days since 0001-01-01 as `YYYY-MM-DD`.
+fn format_date(days: i64) -> String {
+    // civil_from_days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days - 719_162 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Format nanoseconds since midnight as `HH:MM:SS[.fraction]` with `scale` fractional digits.
+fn format_time(nanos: u64, scale: u8) -> String {
+    let secs = nanos / 1_000_000_000;
+    let mut out = format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+    if scale > 0 {
+        let fraction = format!("{:09}", nanos % 1_000_000_000);
+        out.push('.');
+        out.push_str(&fraction[..scale.min(9) as usize]);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use tiberius::time::{DateTime, SmallDateTime};
+    use tiberius::ColumnData;
+
+    use super::*;
+
+    #[test]
+    fn date() {
+        assert_eq!(format_date(0), "0001-01-01");
+        assert_eq!(format_date(DAYS_TO_1900), "1900-01-01");
+        assert_eq!(format_date(DAYS_TO_1900 + 59), "1900-03-01");
+        assert_eq!(format_date(730_178), "2000-02-29");
+        assert_eq!(format_date(738_550), "2023-01-31");
+        assert_eq!(format_date(3_652_058), "9999-12-31");
+    }
+
+    #[test]
+    fn time() {
+        assert_eq!(format_time(0, 0), "00:00:00");
+        assert_eq!(format_time(43_200_000_000_000, 0), "12:00:00");
+        assert_eq!(format_time(86_399_999_999_999, 7), "23:59:59.9999999");
+        assert_eq!(format_time(3_723_004_000_000, 3), "01:02:03.004");
+        assert_eq!(format_time(1, 12), "00:00:00.000000001");
+    }
+
+    #[test]
+    fn datetime() {
+        let data = |fragments| ColumnData::DateTime(Some(DateTime::new(45_000, fragments)));
+        assert_eq!(format_csv(&data(0)), "2023-03-17 00:00:00.000");
+        // 12:00:00.007 is stored as 2 ticks of 1/300 s past 12:00.
+        assert_eq!(format_csv(&data(12_960_002)), "2023-03-17 12:00:00.007");
+        assert_eq!(format_csv(&data(12_960_001)), "2023-03-17 12:00:00.003");
+        assert_eq!(format_csv(&data(25_919_999)), "2023-03-17 23:59:59.997");
+    }
+
+    #[test]
+    fn smalldatetime() {
+        let data = |minutes| ColumnData::SmallDateTime(Some(SmallDateTime::new(45_000, minutes)));
+        assert_eq!(format_csv(&data(0)), "2023-03-17 00:00:00");
+        assert_eq!(format_csv(&data(720)), "2023-03-17 12:00:00");
+        assert_eq!(format_csv(&data(1_439)), "2023-03-17 23:59:00");
+        assert_eq!(format_csv(&ColumnData::SmallDateTime(None)), "");
+    }
+
+    #[test]
+    fn csv_escaping() {
+        assert_eq!(escape_csv("plain"), "plain");
+        assert_eq!(escape_csv(""), "");
+        assert_eq!(escape_csv("a,b"), "\"a,b\"");
+        assert_eq!(escape_csv("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape_csv("two\nlines"), "\"two\nlines\"");
+        assert_eq!(escape_csv("crlf\r\n\"end\""), "\"crlf\r\n\"\"end\"\"\"");
+    }
+}
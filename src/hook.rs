@@ -0,0 +1,83 @@
+use std::any::Any;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use deadpool::managed::{Hook, HookError, HookFuture, HookResult, Metrics};
+use futures_util::FutureExt;
+
+use crate::{Client, Manager, SqlServerError};
+
+/// Builds the [`Hook`] registered on a pool, given the flag of
+/// [`Manager::catch_hook_panics`](crate::Manager::catch_hook_panics) of its manager.
+///
+/// Kept instead of the [`Hook`] itself so a [`Manager`] can be cloned.
+pub(crate) type HookFactory = Arc<dyn Fn(&Arc<AtomicBool>) -> Hook<Manager> + Send + Sync>;
+
+/// Factory of a sync hook, see [`guard_sync`].
+pub(crate) fn sync_factory<T>(hook: T) -> HookFactory
+where
+    T: Fn(&mut Client, &Metrics) -> HookResult<SqlServerError> + Sync + Send + 'static,
+{
+    let hook = Arc::new(hook);
+    Arc::new(move |catch| guard_sync(catch.clone(), hook.clone()))
+}
+
+/// Factory of an async hook, see [`guard_async`].
+pub(crate) fn async_factory<T>(hook: T) -> HookFactory
+where
+    T: for<'a> Fn(&'a mut Client, &'a Metrics) -> HookFuture<'a, SqlServerError>
+        + Sync
+        + Send
+        + 'static,
+{
+    let hook = Arc::new(hook);
+    Arc::new(move |catch| guard_async(catch.clone(), hook.clone()))
+}
+
+/// Wrap a sync hook, turning its panics into errors while `catch` is set.
+fn guard_sync<T>(catch: Arc<AtomicBool>, hook: Arc<T>) -> Hook<Manager>
+where
+    T: Fn(&mut Client, &Metrics) -> HookResult<SqlServerError> + Sync + Send + 'static,
+{
+    Hook::sync_fn(move |client: &mut Client, metrics: &Metrics| {
+        if !catch.load(Ordering::Relaxed) {
+            return hook(client, metrics);
+        }
+        catch_unwind(AssertUnwindSafe(|| hook(client, metrics)))
+            .unwrap_or_else(|payload| Err(HookError::Backend(panicked(payload))))
+    })
+}
+
+/// Wrap an async hook, turning its panics into errors while `catch` is set.
+fn guard_async<T>(catch: Arc<AtomicBool>, hook: Arc<T>) -> Hook<Manager>
+where
+    T: for<'a> Fn(&'a mut Client, &'a Metrics) -> HookFuture<'a, SqlServerError>
+        + Sync
+        + Send
+        + 'static,
+{
+    Hook::async_fn(move |client: &mut Client, metrics: &Metrics| {
+        if !catch.load(Ordering::Relaxed) {
+            return hook(client, metrics);
+        }
+        let hook = hook.clone();
+        Box::pin(
+            AssertUnwindSafe(async move { hook(client, metrics).await })
+                .catch_unwind()
+                .map(|result| {
+                    result.unwrap_or_else(|payload| Err(HookError::Backend(panicked(payload))))
+                }),
+        )
+    })
+}
+
+/// Error for a caught panic, carrying its message if it has one.
+pub(crate) fn panicked(payload: Box<dyn Any + Send>) -> SqlServerError {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Box<dyn Any>".to_string());
+    SqlServerError::HookPanicked(message)
+}
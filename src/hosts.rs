@@ -0,0 +1,159 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a host which failed to connect is tried only after the healthy ones.
+const FAILED_HOST_BACKOFF: Duration = Duration::from_secs(30);
+
+/// IP address family to connect with, see
+/// [`Manager::address_family`](crate::Manager::address_family).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AddrFamily {
+    /// Any family, trying the resolved addresses in order.
+    #[default]
+    Any,
+    /// IPv4 only.
+    V4,
+    /// IPv6 only.
+    V6,
+}
+
+impl AddrFamily {
+    pub(crate) fn matches(self, addr: &SocketAddr) -> bool {
+        match self {
+            AddrFamily::Any => true,
+            AddrFamily::V4 => addr.is_ipv4(),
+            AddrFamily::V6 => addr.is_ipv6(),
+        }
+    }
+}
+
+/// Candidate hosts of a [`Manager`](crate::Manager), see
+/// [`Manager::weighted_hosts`](crate::Manager::weighted_hosts) and
+/// [`Manager::hosts`](crate::Manager::hosts).
+#[derive(Default)]
+pub(crate) struct HostSet {
+    hosts: Vec<Host>,
+    /// Failover in order instead of weighted random selection.
+    ordered: bool,
+    /// Host which connected last, tried first when `ordered`.
+    preferred: AtomicUsize,
+}
+
+struct Host {
+    /// `None` for the host configured on the manager itself.
+    addr: Option<(String, u16)>,
+    weight: u32,
+    failed_at: Mutex<Option<Instant>>,
+}
+
+/// Clones the hosts only, forgetting which ones failed or connected last.
+impl Clone for HostSet {
+    fn clone(&self) -> Self {
+        Self {
+            hosts: self
+                .hosts
+                .iter()
+                .map(|host| Host::new(host.addr.clone(), host.weight))
+                .collect(),
+            ordered: self.ordered,
+            preferred: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Host {
+    fn new(addr: Option<(String, u16)>, weight: u32) -> Self {
+        Self {
+            addr,
+            weight,
+            failed_at: Mutex::new(None),
+        }
+    }
+
+    fn is_failing(&self) -> bool {
+        self.failed_at
+            .lock()
+            .unwrap()
+            .is_some_and(|at| at.elapsed() < FAILED_HOST_BACKOFF)
+    }
+}
+
+impl HostSet {
+    pub(crate) fn weighted(hosts: &[(impl ToString, u16, u32)]) -> Self {
+        Self {
+            hosts: hosts
+                .iter()
+                .map(|(host, port, weight)| Host::new(Some((host.to_string(), *port)), *weight))
+                .collect(),
+            ordered: false,
+            preferred: AtomicUsize::new(0),
+        }
+    }
+
+    /// The configured host followed by `hosts`, tried in order.
+    pub(crate) fn failover(hosts: Vec<(String, u16)>) -> Self {
+        let hosts = std::iter::once(Host::new(None, 1))
+            .chain(hosts.into_iter().map(|addr| Host::new(Some(addr), 1)))
+            .collect();
+        Self {
+            hosts,
+            ordered: true,
+            preferred: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.hosts.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+
+    pub(crate) fn has_zero_weight(&self) -> bool {
+        self.hosts.iter().any(|host| host.weight == 0)
+    }
+
+    /// Indices of the hosts in the order to try them: healthy hosts first, each group in list
+    /// order starting with the preferred host when ordered, in weighted random order otherwise.
+    pub(crate) fn candidates(&self) -> Vec<usize> {
+        let preferred = self.preferred.load(Ordering::Relaxed);
+        let mut keyed: Vec<(bool, f64, usize)> = self
+            .hosts
+            .iter()
+            .enumerate()
+            .map(|(idx, host)| {
+                let key = if !self.ordered {
+                    // Weighted sampling without replacement (Efraimidis-Spirakis).
+                    fastrand::f64().powf(1.0 / host.weight as f64)
+                } else if idx == preferred {
+                    f64::INFINITY
+                } else {
+                    -(idx as f64)
+                };
+                (host.is_failing(), key, idx)
+            })
+            .collect();
+        keyed.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.total_cmp(&a.1)));
+        keyed.into_iter().map(|(_, _, idx)| idx).collect()
+    }
+
+    /// Address of the host at `idx`, `None` for the host configured on the manager.
+    pub(crate) fn addr(&self, idx: usize) -> Option<(&str, u16)> {
+        self.hosts[idx]
+            .addr
+            .as_ref()
+            .map(|(host, port)| (host.as_str(), *port))
+    }
+
+    pub(crate) fn succeeded(&self, idx: usize) {
+        *self.hosts[idx].failed_at.lock().unwrap() = None;
+        self.preferred.store(idx, Ordering::Relaxed);
+    }
+
+    pub(crate) fn failed(&self, idx: usize) {
+        *self.hosts[idx].failed_at.lock().unwrap() = Some(Instant::now());
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use deadpool::managed::Object;
+use futures_util::future::BoxFuture;
+use tokio::sync::{Mutex, MutexGuard};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{Manager, Pool, SqlServerError};
+
+/// Boxed error of [`PoolService`], following tower's convention.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A [`Layer`] providing each request with a pooled connection, see [`PoolService`].
+///
+/// With axum, handlers take the connection through `Extension<PooledConnection>`.
+/// ```no_run
+/// # fn f(pool: deadpool_tiberius::Pool) {
+/// use deadpool_tiberius::{BoxError, PoolLayer, PooledConnection};
+/// use tower::ServiceBuilder;
+///
+/// let service = ServiceBuilder::new()
+///     .layer(PoolLayer::new(pool))
+///     .service_fn(|req: http::Request<()>| async move {
+///         let conn = req.extensions().get::<PooledConnection>().unwrap().clone();
+///         conn.lock().await.simple_query("SELECT 1").await?;
+///         Ok::<_, BoxError>(http::Response::new(()))
+///     });
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct PoolLayer {
+    pool: Pool,
+}
+
+impl PoolLayer {
+    /// Layer acquiring connections from `pool`.
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+impl<S> Layer<S> for PoolLayer {
+    type Service = PoolService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PoolService {
+            inner,
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+/// A [`Service`] acquiring a connection before calling the inner service, inserting it into the
+/// request extensions as [`PooledConnection`].
+///
+/// The connection is acquired for every request, whether the handler uses it or not, and returns
+/// to the pool once the request and all clones of the [`PooledConnection`] are dropped. Failing
+/// to acquire one fails the request with the [`SqlServerError`] as [`BoxError`], without calling
+/// the inner service.
+#[derive(Clone)]
+pub struct PoolService<S> {
+    inner: S,
+    pool: Pool,
+}
+
+impl<S, B> Service<http::Request<B>> for PoolService<S>
+where
+    S: Service<http::Request<B>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<S::Response, BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        // Take the service which was driven to readiness, leaving a fresh clone behind.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let pool = self.pool.clone();
+        Box::pin(async move {
+            let conn = pool.get().await.map_err(SqlServerError::from)?;
+            req.extensions_mut()
+                .insert(PooledConnection(Arc::new(Mutex::new(conn))));
+            inner.call(req).await.map_err(Into::into)
+        })
+    }
+}
+
+/// A pooled connection inserted into the request extensions by [`PoolService`].
+#[derive(Clone)]
+pub struct PooledConnection(Arc<Mutex<Object<Manager>>>);
+
+impl PooledConnection {
+    /// Lock the connection for exclusive use.
+    pub async fn lock(&self) -> MutexGuard<'_, Object<Manager>> {
+        self.0.lock().await
+    }
+}
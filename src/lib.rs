@@ -1,56 +1,305 @@
 //! This crate chains config from [`tiberius`] and [`deadpool`] to make it easier for creating tiberius connection pool.
 //! # Example
 //! ```no_run
+//! # use std::time::Duration;
 //! let pool = deadpool_tiberius::Manager::new()
 //!     .host("host")
 //!     .port(1433)
 //!     .basic_authentication("username", "password")
 //!     .database("database1")
 //!     .max_size(20)
-//!     .wait_timeout(1.52)
+//!     .wait_timeout(Duration::from_secs_f64(1.52))
 //!     .pre_recycle_sync(|_client, _metrics| {
 //!         // do sth with connection object and pool metrics.
 //!         Ok(())
 //!     })
 //!     .create_pool()?;
+//! # Ok::<_, deadpool_tiberius::SqlServerError>(())
 //! ```
 //!
 //! [`Manager::from_ado_string`] and [`Manager::from_jdbc_string`] also served as another entry for constructing Manager.
 //! ```no_run
+//! # use std::time::Duration;
 //! const CONN_STR: &str = "Driver={SQL Server};Integrated Security=True;\
 //!                         Server=DESKTOP-TTTTTTT;Database=master;\
 //!                         Trusted_Connection=yes;encrypt=DANGER_PLAINTEXT;";
 //! let pool = deadpool_tiberius::Manager::from_ado_string(CONN_STR)?
 //!                 .max_size(20)
-//!                 .wait_timeout(1.52)
+//!                 .wait_timeout(Duration::from_secs_f64(1.52))
 //!                 .create_pool()?;
+//! # Ok::<_, deadpool_tiberius::SqlServerError>(())
 //! ```
 //! For all configurable pls visit [`Manager`].
+//!
+//! # Runtimes
+//! Connections are established with tokio's networking, and the pool timeouts run on tokio's
+//! timers by default. With the `runtime-async-std` feature the timeouts run on async-std
+//! instead, connecting still requires a tokio reactor then, e.g. by running the pool calls
+//! within `async_compat::Compat`. Background tasks, such as
+//! [`Manager::idle_ping_interval`], are always spawned on tokio. [`Manager::runtime`] picks the
+//! runtime of the timeouts explicitly.
+//!
+//! # TLS
+//! The TLS backend is picked with a feature, forwarded to tiberius:
+//! - `native-tls` (default): the platform's TLS, i.e. SChannel on Windows, Security.framework on
+//!   macOS and OpenSSL elsewhere.
+//! - `rustls`: pure Rust, avoiding OpenSSL, e.g. in musl or Alpine based containers. Disable the
+//!   default features to drop `native-tls`:
+//!   `deadpool-tiberius = { version = "*", default-features = false, features = ["rustls", "tds73"] }`
+//! - `vendored-openssl`: OpenSSL built from source and linked statically.
+//!
+//! With any backend, [`EncryptionLevel::Required`] (the default) encrypts the whole connection,
+//! [`EncryptionLevel::On`] encrypts if the server supports it, [`EncryptionLevel::Off`] encrypts
+//! the login only and [`EncryptionLevel::NotSupported`] nothing. [`Manager::trust_cert`] and
+//! [`Manager::trust_cert_ca`] behave the same across backends, except that `rustls` requires the
+//! host to be a valid DNS name or IP address unless the certificate is trusted blindly. Without
+//! a backend only [`EncryptionLevel::NotSupported`] works, [`Manager::create_pool`] fails with
+//! [`SqlServerError::Unsupported`] when encryption is requested, instead of sending the login in
+//! plain text.
+//!
+//! # Tracing
+//! With the `tracing` feature, each connection created runs in a `create` span recording the
+//! configured host and port and the outcome. Routing redirects and failing hosts are logged at
+//! `DEBUG` and `WARN` level, as are failed recycles. Without the feature, nothing is emitted.
+//!
+//! # Informational messages
+//! Messages from `PRINT` and `RAISERROR` with severity 10 or lower are not errors. tiberius
+//! consumes them while reading a response: they neither terminate a result stream nor fail a
+//! query, so a stored procedure reporting progress before its result set still returns the
+//! result set. They are not handed to the caller, but emitted as `tracing` events at `INFO`
+//! level under the `tiberius` target, install a `tracing` subscriber to collect them.
+//!
+//! # Streaming large results
+//! tiberius has no prefetch or row buffer setting to tune. A [`tiberius::QueryStream`] decodes
+//! rows as the TDS packets (4 KiB by default) arrive, holding at most the packet being decoded
+//! besides the socket buffers, and reads no further than it is polled. Polling the stream row by
+//! row therefore gives the lowest first-row latency and flat memory use, while
+//! `into_first_result`/`into_results` trade memory for collecting everything in one go. To work
+//! in batches, group the polled rows on the client side, e.g. with `StreamExt::chunks` from
+//! `futures-util` on `into_row_stream()`. The server keeps sending regardless of batching,
+//! throttled by TCP flow control when the client falls behind.
+//!
+//! # Unsupported features
+//! Some features of the SQL Server drivers have no counterpart in tiberius, hence none here:
+//! - Always Encrypted (`Column Encryption Setting=Enabled`): tiberius can neither encrypt
+//!   parameters nor decrypt protected columns, such columns read as their ciphertext.
+//! - `Packet Size`: tiberius always requests packets of 4096 bytes at login, the packet size
+//!   can't be configured.
+//! - Strict encryption (`Encrypt=Strict`, TDS 8.0): tiberius only negotiates TLS within the TDS
+//!   prelogin, servers enforcing strict encryption refuse its connections.
+//! - Client certificates (mutual TLS): neither TLS backend of tiberius presents one, servers
+//!   requiring a client certificate refuse its connections.
 #![warn(missing_docs)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::mem::replace;
-use std::time::Duration;
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
+use connection_string::{AdoNetString, JdbcString};
 pub use deadpool;
+pub use deadpool::managed::Timeouts;
+pub use deadpool::Runtime;
+pub use deadpool::Status;
 use deadpool::{
     async_trait, managed,
-    managed::{Hook, HookFuture, HookResult, Metrics, PoolConfig, RecycleError, RecycleResult},
-    Runtime,
+    managed::{
+        Hook, HookError, HookFuture, HookResult, Metrics, PoolConfig, RecycleError, RecycleResult,
+    },
 };
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
 pub use tiberius;
 use tiberius::error::Error;
 use tiberius::{AuthMethod, EncryptionLevel};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_util::compat::TokioAsyncWriteCompatExt;
 
+#[cfg(feature = "admin")]
+#[cfg_attr(docsrs, doc(cfg(feature = "admin")))]
+pub use crate::admin::{
+    self_test, BlockingEntry, DatabaseSize, SelfTestReport, Session, SystemQueries,
+};
+use crate::approle::ApplicationRole;
+use crate::breaker::CircuitBreaker;
+pub use crate::client_ext::ClientExt;
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub use crate::config::ManagerConfig;
+pub use crate::edition::ServerEdition;
+pub use crate::error::ConnectPhase;
 pub use crate::error::SqlServerError;
 pub use crate::error::SqlServerResult;
+pub use crate::error::TimeoutKind;
+pub use crate::export::{export_query, ExportFormat};
+use crate::hook::HookFactory;
+pub use crate::hosts::AddrFamily;
+use crate::hosts::HostSet;
+#[cfg(feature = "tower")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+pub use crate::layer::{BoxError, PoolLayer, PoolService, PooledConnection};
+use crate::maintenance::Duties;
+#[cfg(feature = "testing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+pub use crate::mock::{MockResult, MockValue};
+pub use crate::observer::{ObservedConnection, PoolObserver};
+pub use crate::pool_ext::{PoolBulkExt, PoolExt, PoolQueryExt};
+pub use crate::read_write::ReadWritePool;
+use crate::stats::StatsCounters;
+pub use crate::stats::{lifetime_stats, LifetimeStats};
+pub use crate::swap::SwappablePool;
+use crate::taint::{Taint, Taints};
+pub use crate::transaction::{Transaction, TransactionGuard};
 
+#[cfg(feature = "admin")]
+mod admin;
+mod ado;
+mod approle;
+mod breaker;
+mod client_ext;
+#[cfg(feature = "serde")]
+mod config;
+mod diagnose;
+mod edition;
 mod error;
+mod export;
+mod hook;
+mod hosts;
+#[cfg(feature = "tower")]
+mod layer;
+mod maintenance;
+#[cfg(feature = "testing")]
+mod mock;
+mod named;
+mod observer;
+mod pool_ext;
+mod read_write;
+mod stats;
+mod swap;
+mod taint;
+mod transaction;
+mod url;
 
 /// Type aliasing for tiberius client with [`tokio`] as runtime.
-pub type Client = tiberius::Client<tokio_util::compat::Compat<tokio::net::TcpStream>>;
-/// Type aliasing for Pool.
-pub type Pool = managed::Pool<Manager>;
+pub type Client = tiberius::Client<tokio_util::compat::Compat<BoxedStream>>;
+
+/// Transport of a [`Client`], see [`Manager::with_stream_wrapper`].
+pub type BoxedStream = Box<dyn AsyncStream>;
+
+/// A bidirectional byte stream a [`Client`] can run on, implemented for all suitable types.
+pub trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> AsyncStream for T {}
+/// Pool built by [`Manager::create_pool`], dereferencing to the [`managed::Pool`] it wraps.
+///
+/// Cloning it is cheap and shares the pool. The pool's background task, see
+/// [`Manager::min_idle`], stops once the last clone is dropped.
+#[derive(Clone)]
+pub struct Pool {
+    inner: managed::Pool<Manager>,
+    /// Counts the clones, the background task only keeps a weak reference.
+    handles: Arc<()>,
+}
+
+impl Deref for Pool {
+    type Target = managed::Pool<Manager>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// Encryption level of a new [`tiberius::Config`].
+#[cfg(any(
+    feature = "rustls",
+    feature = "native-tls",
+    feature = "vendored-openssl"
+))]
+const DEFAULT_ENCRYPTION: EncryptionLevel = EncryptionLevel::Required;
+#[cfg(not(any(
+    feature = "rustls",
+    feature = "native-tls",
+    feature = "vendored-openssl"
+)))]
+const DEFAULT_ENCRYPTION: EncryptionLevel = EncryptionLevel::NotSupported;
+
+/// How often the pool is topped up to [`Manager::min_idle`] idle connections.
+const MIN_IDLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Pause before re-running a recycle probe which failed transiently, see
+/// [`Manager::recycle_retry`].
+const RECYCLE_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Restores the session defaults of a fresh connection, see [`Manager::reset_on_recycle`].
+///
+/// Temp tables are listed in `tempdb` under their name padded with `_` and a suffix, the
+/// padding is trimmed to look them up by name, which only finds those of this session.
+const RESET_SESSION: &str = "SET ANSI_NULLS ON;
+SET ANSI_PADDING ON;
+SET ANSI_WARNINGS ON;
+SET ARITHABORT ON;
+SET CONCAT_NULL_YIELDS_NULL ON;
+SET QUOTED_IDENTIFIER ON;
+SET NUMERIC_ROUNDABORT OFF;
+SET IMPLICIT_TRANSACTIONS OFF;
+SET XACT_ABORT OFF;
+SET NOCOUNT OFF;
+SET TEXTSIZE 2147483647;
+SET LOCK_TIMEOUT -1;
+SET DEADLOCK_PRIORITY NORMAL;
+SET TRANSACTION ISOLATION LEVEL READ COMMITTED;
+DECLARE @drop_temp_tables nvarchar(max) = N'';
+SELECT @drop_temp_tables += N'DROP TABLE ' + QUOTENAME(short_name) + N';'
+FROM (
+    SELECT object_id, LEFT(padded, LEN(padded) - PATINDEX('%[^_]%', REVERSE(padded)) + 1)
+    FROM (SELECT object_id, LEFT(name, 116) FROM tempdb.sys.tables
+        WHERE name LIKE N'#%' AND name NOT LIKE N'##%') AS temp_names (object_id, padded)
+) AS temp_tables (object_id, short_name)
+WHERE OBJECT_ID(N'tempdb..' + short_name) = object_id;
+EXEC sp_executesql @drop_temp_tables;
+";
+
+/// Redirects followed when connecting, see [`SqlServerError::TooManyRedirects`].
+const MAX_REDIRECTS: usize = 5;
+
+/// Number of the next pool built with a [`Manager::session_tag`].
+static NEXT_TAGGED_POOL: AtomicUsize = AtomicUsize::new(1);
+
+/// Runtime driving the pool timeouts.
+#[cfg(not(feature = "runtime-async-std"))]
+const TIMEOUT_RUNTIME: Runtime = Runtime::Tokio1;
+#[cfg(feature = "runtime-async-std")]
+const TIMEOUT_RUNTIME: Runtime = Runtime::AsyncStd1;
+
+type StreamWrapper = dyn Fn(tokio::net::TcpStream) -> BoxFuture<'static, tokio::io::Result<BoxedStream>>
+    + Send
+    + Sync
+    + 'static;
+
+type OnCreate =
+    dyn for<'a> Fn(&'a mut Client) -> BoxFuture<'a, SqlServerResult<()>> + Send + Sync + 'static;
+
+type RecycleCheck = dyn for<'a> Fn(&'a mut Client, &'a Metrics) -> BoxFuture<'a, RecycleResult<SqlServerError>>
+    + Send
+    + Sync
+    + 'static;
+
+type Resolver =
+    dyn Fn(&str) -> BoxFuture<'static, tokio::io::Result<Vec<SocketAddr>>> + Send + Sync + 'static;
+
+type MetricsCallback = dyn Fn(Status) + Send + Sync + 'static;
+
+#[cfg(feature = "azure")]
+type TokenProvider = dyn Fn() -> BoxFuture<'static, Result<String, Box<dyn std::error::Error + Send + Sync>>>
+    + Send
+    + Sync
+    + 'static;
 
 /// Connection pool Manager served as Builder. Call [`create_pool`] after filling out your configs.
 ///
@@ -60,76 +309,377 @@ pub struct Manager {
     pool_config: PoolConfig,
     runtime: Option<Runtime>,
     hooks: Hooks,
+    #[allow(clippy::type_complexity)]
     modify_tcp_stream:
-        Box<dyn Fn(&tokio::net::TcpStream) -> tokio::io::Result<()> + Send + Sync + 'static>,
+        Arc<dyn Fn(&tokio::net::TcpStream) -> tokio::io::Result<()> + Send + Sync + 'static>,
     #[cfg(feature = "sql-browser")]
     enable_sql_browser: bool,
+    diagnose_on_failure: bool,
+    database: Option<String>,
+    reset_database_on_recycle: bool,
+    reset_on_recycle: bool,
+    on_create: Option<Arc<OnCreate>>,
+    verify_encryption: bool,
+    resolver: Option<Arc<Resolver>>,
+    stats: Arc<StatsCounters>,
+    create_error_cache: Option<Duration>,
+    circuit_breaker: Option<CircuitBreaker>,
+    cached_create_error: Mutex<Option<(Instant, String)>>,
+    pub(crate) taints: Taints,
+    /// Connections opened by the background task, handed to the pool by the next creates.
+    pub(crate) opened: Mutex<Vec<Client>>,
+    trust_cert_ca: Option<PathBuf>,
+    idle_ping_interval: Option<Duration>,
+    min_idle: usize,
+    detect_server_edition: bool,
+    server_edition: OnceLock<ServerEdition>,
+    hosts: HostSet,
+    address_family: AddrFamily,
+    multi_subnet_failover: bool,
+    /// Instance set with [`Manager::named_instance`], validated when building the pool.
+    named_instance: Option<String>,
+    textsize: Option<i32>,
+    query_comment: Option<String>,
+    session_tag: Option<String>,
+    catch_hook_panics: Arc<AtomicBool>,
+    application_role: Option<ApplicationRole>,
+    recycle_query: Option<Arc<str>>,
+    recycle_check: Option<Arc<RecycleCheck>>,
+    recycle_attempts: u32,
+    recycle_query_timeout: Option<Duration>,
+    recycle_min_idle: Option<Duration>,
+    max_connection_age: Option<Duration>,
+    tcp_keepalive: Option<(bool, Option<Duration>)>,
+    socket_buffers: (Option<usize>, Option<usize>),
+    metrics_callback: Option<(Duration, Arc<MetricsCallback>)>,
+    pub(crate) observer: Option<Arc<dyn PoolObserver>>,
+    #[cfg(feature = "azure")]
+    aad_token_provider: Option<Arc<TokenProvider>>,
+    prefill: usize,
+    connect_retries: u32,
+    connect_backoff: Duration,
+    connect_timeout: Option<Duration>,
+    stream_wrapper: Option<Arc<StreamWrapper>>,
+    /// Unix domain socket or named pipe connected to instead of TCP.
+    local_socket: Option<PathBuf>,
+    init_sql: Vec<String>,
+    lock_timeout: Option<u32>,
+    query_timeout: Option<Duration>,
+    /// Whether an authentication method was configured, tiberius defaults to none.
+    authentication_set: bool,
+    /// Whether the configured authentication method may hold an AAD token, to redact.
+    redact_auth: bool,
+    /// Password of the configured login if known, tiberius doesn't expose it, see
+    /// [`Manager::to_ado_string`].
+    password: Option<String>,
+    /// User of the configured login if known, prefixed with the domain of Windows logins.
+    user: Option<String>,
+    /// Whether the configured login uses Windows or integrated authentication.
+    integrated_security: bool,
+    // Settings of `config` tiberius doesn't expose, kept for `to_ado_string`.
+    host: Option<String>,
+    port: Option<u16>,
+    instance_name: Option<String>,
+    application_name: Option<String>,
+    encryption: EncryptionLevel,
+    trust_cert: bool,
+    readonly: bool,
+    #[cfg(feature = "testing")]
+    mock: Option<Arc<mock::MockServer>>,
+}
+
+/// Clones the configuration, e.g. to build several similar pools from a template.
+///
+/// Runtime state such as [`lifetime_stats`], a cached create error, the detected
+/// [`server_edition`] and failing hosts is not carried over.
+///
+/// [`server_edition`]: Manager::server_edition
+impl Clone for Manager {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            pool_config: self.pool_config,
+            runtime: self.runtime,
+            hooks: self.hooks.clone(),
+            modify_tcp_stream: self.modify_tcp_stream.clone(),
+            #[cfg(feature = "sql-browser")]
+            enable_sql_browser: self.enable_sql_browser,
+            diagnose_on_failure: self.diagnose_on_failure,
+            database: self.database.clone(),
+            reset_database_on_recycle: self.reset_database_on_recycle,
+            reset_on_recycle: self.reset_on_recycle,
+            on_create: self.on_create.clone(),
+            verify_encryption: self.verify_encryption,
+            resolver: self.resolver.clone(),
+            stats: Default::default(),
+            create_error_cache: self.create_error_cache,
+            circuit_breaker: self.circuit_breaker.clone(),
+            cached_create_error: Mutex::new(None),
+            taints: Taints::default(),
+            opened: Mutex::new(Vec::new()),
+            trust_cert_ca: self.trust_cert_ca.clone(),
+            idle_ping_interval: self.idle_ping_interval,
+            min_idle: self.min_idle,
+            detect_server_edition: self.detect_server_edition,
+            server_edition: OnceLock::new(),
+            hosts: self.hosts.clone(),
+            address_family: self.address_family,
+            multi_subnet_failover: self.multi_subnet_failover,
+            named_instance: self.named_instance.clone(),
+            textsize: self.textsize,
+            query_comment: self.query_comment.clone(),
+            session_tag: self.session_tag.clone(),
+            catch_hook_panics: Arc::new(AtomicBool::new(
+                self.catch_hook_panics.load(Ordering::Relaxed),
+            )),
+            application_role: self.application_role.clone(),
+            recycle_query: self.recycle_query.clone(),
+            recycle_check: self.recycle_check.clone(),
+            recycle_attempts: self.recycle_attempts,
+            recycle_query_timeout: self.recycle_query_timeout,
+            recycle_min_idle: self.recycle_min_idle,
+            max_connection_age: self.max_connection_age,
+            tcp_keepalive: self.tcp_keepalive,
+            socket_buffers: self.socket_buffers,
+            metrics_callback: self.metrics_callback.clone(),
+            observer: self.observer.clone(),
+            #[cfg(feature = "azure")]
+            aad_token_provider: self.aad_token_provider.clone(),
+            prefill: self.prefill,
+            connect_retries: self.connect_retries,
+            connect_backoff: self.connect_backoff,
+            connect_timeout: self.connect_timeout,
+            stream_wrapper: self.stream_wrapper.clone(),
+            local_socket: self.local_socket.clone(),
+            init_sql: self.init_sql.clone(),
+            lock_timeout: self.lock_timeout,
+            query_timeout: self.query_timeout,
+            authentication_set: self.authentication_set,
+            redact_auth: self.redact_auth,
+            password: self.password.clone(),
+            user: self.user.clone(),
+            integrated_security: self.integrated_security,
+            host: self.host.clone(),
+            port: self.port,
+            instance_name: self.instance_name.clone(),
+            application_name: self.application_name.clone(),
+            encryption: self.encryption,
+            trust_cert: self.trust_cert,
+            readonly: self.readonly,
+            #[cfg(feature = "testing")]
+            mock: self.mock.clone(),
+        }
+    }
+}
+
+impl From<tiberius::Config> for Manager {
+    fn from(config: tiberius::Config) -> Self {
+        Self::from_config(config)
+    }
+}
+
+/// Lists the main settings, with credentials redacted as in [`Manager::debug_config`].
+impl std::fmt::Debug for Manager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("Manager");
+        debug
+            .field("config", &format_args!("{}", self.debug_config()))
+            .field("pool_config", &self.pool_config)
+            .field("database", &self.database)
+            .field("hosts", &self.hosts.len())
+            .field("local_socket", &self.local_socket)
+            .field(
+                "application_role",
+                &self.application_role.as_ref().map(|role| role.name()),
+            )
+            .field("min_idle", &self.min_idle)
+            .field("prefill", &self.prefill)
+            .field("connect_retries", &self.connect_retries)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("tcp_keepalive", &self.tcp_keepalive)
+            .field("max_connection_age", &self.max_connection_age);
+        #[cfg(feature = "azure")]
+        debug.field("aad_token_provider", &self.aad_token_provider.is_some());
+        debug.finish_non_exhaustive()
+    }
 }
 
 #[async_trait]
 impl managed::Manager for Manager {
     type Type = Client;
-    type Error = tiberius::error::Error;
+    type Error = SqlServerError;
 
-    #[cfg(feature = "sql-browser")]
     async fn create(&self) -> Result<Client, Self::Error> {
-        use tiberius::SqlBrowser;
-        let tcp = if !self.enable_sql_browser {
-            tokio::net::TcpStream::connect(self.config.get_addr()).await?
-        } else {
-            tokio::net::TcpStream::connect_named(&self.config).await?
+        if let Some(client) = self.opened.lock().unwrap().pop() {
+            return Ok(client);
+        }
+        if let Some(breaker) = &self.circuit_breaker {
+            breaker
+                .check()
+                .map_err(|retry_in| SqlServerError::CircuitOpen { retry_in })?;
+        }
+        if let Some(window) = self.create_error_cache {
+            if let Some((at, message)) = &*self.cached_create_error.lock().unwrap() {
+                if at.elapsed() < window {
+                    return Err(SqlServerError::CreateErrorCached(message.clone()));
+                }
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        let result = self.create_client().await;
+        #[cfg(feature = "tracing")]
+        let result = {
+            use tracing::Instrument;
+            let addr = self.config.get_addr();
+            let (host, port) = addr.rsplit_once(':').unwrap_or((&addr, ""));
+            let span = tracing::debug_span!("create", host, port, outcome = tracing::field::Empty);
+            let result = self.create_client().instrument(span.clone()).await;
+            match &result {
+                Ok(_) => span.record("outcome", "ok"),
+                Err(e) => span.record("outcome", tracing::field::display(e)),
+            };
+            result
         };
-        (self.modify_tcp_stream)(&tcp)?;
-        let client = Client::connect(self.config.clone(), tcp.compat_write()).await;
-        match client {
-            Ok(client) => Ok(client),
-            Err(Error::Routing { host, port }) => {
-                let mut config = self.config.clone();
-                config.host(host);
-                config.port(port);
-
-                let tcp = tokio::net::TcpStream::connect(config.get_addr()).await?;
-                tcp.set_nodelay(true)?;
-
-                Client::connect(config, tcp.compat_write()).await
-            },
-            // Propagate errors
-            Err(err) => Err(err)?,
+        if let Some(breaker) = &self.circuit_breaker {
+            breaker.record(result.is_ok());
+        }
+        if let (Some(observer), Err(e)) = (&self.observer, &result) {
+            observer.on_create_error(e);
         }
+        if self.create_error_cache.is_some() {
+            *self.cached_create_error.lock().unwrap() = result
+                .as_ref()
+                .err()
+                .map(|e| (Instant::now(), e.to_string()));
+        }
+        result
     }
 
-    #[cfg(not(feature = "sql-browser"))]
-    async fn create(&self) -> Result<Client, Self::Error> {
-        let tcp = tokio::net::TcpStream::connect(self.config.get_addr()).await?;
-        (self.modify_tcp_stream)(&tcp)?;
-        let client = Client::connect(self.config.clone(), tcp.compat_write()).await;
-
-        match client {
-            Ok(client) => Ok(client),
-            Err(Error::Routing { host, port }) => {
-                let mut config = self.config.clone();
-                config.host(host);
-                config.port(port);
-
-                let tcp = tokio::net::TcpStream::connect(config.get_addr()).await?;
-                tcp.set_nodelay(true)?;
-
-                Client::connect(config, tcp.compat_write()).await
-            },
-            // Propagate errors
-            Err(err) => Err(err)?,
+    async fn recycle(&self, obj: &mut Self::Type, metrics: &Metrics) -> RecycleResult<Self::Error> {
+        let last_used = metrics.last_used();
+        let taint = self.taints.take(metrics.created);
+        if let Some(max_age) = self.max_connection_age {
+            if metrics.created.elapsed() > max_age {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(age = ?metrics.created.elapsed(), "max connection age exceeded");
+                self.recycled(false);
+                return Err(RecycleError::StaticMessage("max connection age exceeded"));
+            }
+        }
+        if taint == Some(Taint::Transaction) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("discarding connection dropped with an open transaction");
+            self.recycled(false);
+            return Err(RecycleError::StaticMessage(
+                "connection has an open transaction",
+            ));
+        }
+        if let Some(check) = &self.recycle_check {
+            let result = if self.catch_hook_panics.load(Ordering::Relaxed) {
+                AssertUnwindSafe(check(obj, metrics))
+                    .catch_unwind()
+                    .await
+                    .unwrap_or_else(|payload| Err(RecycleError::Backend(hook::panicked(payload))))
+            } else {
+                check(obj, metrics).await
+            };
+            #[cfg(feature = "tracing")]
+            if let Err(e) = &result {
+                tracing::warn!(error = %e, "recycle check failed");
+            }
+            self.recycled(result.is_ok());
+            return result;
+        }
+        if let (Some(min_idle), None) = (self.recycle_min_idle, taint) {
+            if last_used < min_idle {
+                self.recycled(true);
+                return Ok(());
+            }
+        }
+        // Doubles as liveness probe and leaked transaction check.
+        let mut query = match &self.database {
+            Some(database) if self.reset_database_on_recycle => {
+                format!("USE {};\n", quote_identifier(database))
+            }
+            // The login's database isn't known upfront, look up whether it was switched away from.
+            None if self.reset_database_on_recycle => {
+                "SELECT NULLIF(ORIGINAL_DB_NAME(), DB_NAME());\n".to_string()
+            }
+            _ => String::new(),
+        };
+        if self.reset_on_recycle {
+            query.push_str(RESET_SESSION);
+            // Set up again what the reset undid.
+            for sql in self.session_settings() {
+                query.push_str(&sql);
+                query.push('\n');
+            }
+        }
+        if let Some(recycle_query) = &self.recycle_query {
+            query.push_str(recycle_query);
+            query.push('\n');
+        }
+        query.push_str("SELECT @@TRANCOUNT");
+        let probe = async {
+            let mut attempt = 1;
+            loop {
+                let result = match obj.simple_query(&query).await {
+                    Ok(stream) => stream.into_results().await,
+                    Err(e) => Err(e),
+                };
+                match result {
+                    // Errors below severity 20 leave the connection open.
+                    Err(Error::Server(e)) if e.class() < 20 && attempt < self.recycle_attempts => {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(error = %e, attempt, "recycle probe failed, retrying");
+                        attempt += 1;
+                        tokio::time::sleep(RECYCLE_RETRY_DELAY).await;
+                    }
+                    result => break result,
+                }
+            }
+        };
+        let result = match self.recycle_query_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, probe)
+                .await
+                .map_err(|_| RecycleError::StaticMessage("recycle probe timed out")),
+            None => Ok(probe.await),
+        };
+        let result = match result {
+            Ok(Ok(results)) => {
+                let trancount = results
+                    .last()
+                    .and_then(|rows| rows.first())
+                    .and_then(|row| row.get::<i32, _>(0))
+                    .unwrap_or_default();
+                if trancount > 0 {
+                    Err(RecycleError::StaticMessage(
+                        "connection has an open transaction",
+                    ))
+                } else if let Some(database) = self.switched_database(&results) {
+                    obj.use_database(&database)
+                        .await
+                        .map_err(|e| RecycleError::Message(e.to_string()))
+                } else {
+                    Ok(())
+                }
+            }
+            Ok(Err(e)) => Err(RecycleError::Message(e.to_string())),
+            Err(e) => Err(e),
+        };
+        #[cfg(feature = "tracing")]
+        if let Err(e) = &result {
+            tracing::warn!(error = %e, "recycling connection failed");
         }
+        self.recycled(result.is_ok());
+        result
     }
+}
 
-    async fn recycle(
-        &self,
-        obj: &mut Self::Type,
-        _metrics: &Metrics,
-    ) -> RecycleResult<Self::Error> {
-        match obj.simple_query("").await {
-            Ok(_) => Ok(()),
-            Err(e) => Err(RecycleError::Message(e.to_string())),
-        }
+/// Same as [`Manager::new`].
+impl Default for Manager {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -139,24 +689,174 @@ impl Manager {
         Self::new_with_tiberius_config(tiberius::Config::new())
     }
 
+    /// Create a new ConnectionPool Manager from a [`tiberius::Config`] built elsewhere, also
+    /// available as `Manager::from(config)`.
+    ///
+    /// The config's database is selected at login, but unlike [`Manager::database`] not
+    /// restored on recycle.
+    ///
+    /// The config is expected to carry an authentication method, tiberius doesn't expose it for
+    /// [`create_pool`](Manager::create_pool) to check, so a config without one fails at login.
+    /// For the same reason [`debug_config`](Manager::debug_config) shows its authentication
+    /// method as a redacted AAD token, in case it holds one.
+    pub fn from_config(config: tiberius::Config) -> Self {
+        let mut manager = Self::new_with_tiberius_config(config);
+        manager.authentication_set = true;
+        manager.redact_auth = true;
+        manager
+    }
+
     /// Create a new ConnectionPool Manager and fills connection configs from ado string.
     /// For more details about ADO_String pleas refer to [`tiberius::Config::from_ado_string`] and [`Connection Strings in ADO.NET`].
+    /// `Database`/`Initial Catalog` is applied the same way as [`Manager::database`].
+    /// A malformed string fails with [`SqlServerError::InvalidConfig`], which never quotes the
+    /// string as it may hold a password.
     ///
     /// [`Connection Strings in ADO.NET`]: https://docs.microsoft.com/en-us/dotnet/framework/data/adonet/connection-strings
     pub fn from_ado_string(conn_str: &str) -> SqlServerResult<Self> {
-        Ok(Self::new_with_tiberius_config(
-            tiberius::Config::from_ado_string(conn_str)?,
-        ))
+        let config = tiberius::Config::from_ado_string(conn_str).map_err(malformed_conn_str)?;
+        let mut manager = Self::new_with_tiberius_config(config);
+        // Connection strings always yield an authentication method.
+        manager.authentication_set = true;
+        if let Ok(properties) = conn_str.parse::<AdoNetString>() {
+            manager.apply_properties(&properties);
+            let server = properties
+                .get("server")
+                .or_else(|| properties.get("data source"));
+            if let Some(server) = server {
+                let server = server.strip_prefix("tcp:").unwrap_or(server);
+                let (server, port) = match server.split_once(',') {
+                    Some((server, port)) => (server, port.trim().parse().ok()),
+                    None => (server, None),
+                };
+                let (host, instance) = match server.split_once('\\') {
+                    Some((host, instance)) => (host, Some(instance.to_string())),
+                    None => (server, None),
+                };
+                manager.host = Some(host.replace("(local)", "localhost"));
+                manager.port = port;
+                manager.instance_name = instance;
+            }
+        }
+        Ok(manager)
     }
 
     /// Create new ConnectionPool Manager and fills connection config from jdbc string.
     /// For more details about jdbc_string pls refer to [`Building JDBC connection URL`].
     ///
+    /// Malformed strings fail as in [`from_ado_string`](Manager::from_ado_string).
+    ///
     /// [`Building JDBC connection URL`]: https://docs.microsoft.com/en-us/sql/connect/jdbc/building-the-connection-url?view=sql-server-ver15
     pub fn from_jdbc_string(conn_str: &str) -> SqlServerResult<Self> {
-        Ok(Self::new_with_tiberius_config(
-            tiberius::Config::from_jdbc_string(conn_str)?,
-        ))
+        let config = tiberius::Config::from_jdbc_string(conn_str).map_err(malformed_conn_str)?;
+        let mut manager = Self::new_with_tiberius_config(config);
+        // Connection strings always yield an authentication method.
+        manager.authentication_set = true;
+        if let Ok(jdbc) = conn_str.parse::<JdbcString>() {
+            manager.apply_properties(jdbc.properties());
+            manager.host = jdbc.server_name().map(str::to_string);
+            manager.port = jdbc.port();
+            manager.instance_name = jdbc.instance_name().map(str::to_string);
+        }
+        Ok(manager)
+    }
+
+    /// Create a new ConnectionPool Manager from environment variables.
+    ///
+    /// `DATABASE_URL` holds a connection string, read as jdbc string if prefixed with `jdbc:`, as
+    /// URL if prefixed with `mssql://`, see [`from_url`](Manager::from_url), and as ado string
+    /// otherwise. Without it, the connection is configured from `MSSQL_HOST`,
+    /// `MSSQL_USER` and `MSSQL_PASSWORD`, which are required, along with the optional `MSSQL_PORT`
+    /// and `MSSQL_DATABASE`. `MSSQL_POOL_MAX_SIZE` sets [`max_size`] in either case.
+    ///
+    /// A missing required variable fails with [`SqlServerError::MissingEnvVar`], a malformed
+    /// number with [`SqlServerError::InvalidConfig`].
+    ///
+    /// [`max_size`]: Manager::max_size
+    pub fn from_env() -> SqlServerResult<Self> {
+        let mut manager = match env_var("DATABASE_URL") {
+            Some(url) if url.starts_with("jdbc:") => Self::from_jdbc_string(&url)?,
+            Some(url) if url.starts_with("mssql://") => Self::from_url(&url)?,
+            Some(url) => Self::from_ado_string(&url)?,
+            None => {
+                let required = |name| env_var(name).ok_or(SqlServerError::MissingEnvVar(name));
+                let mut manager = Self::new()
+                    .host(required("MSSQL_HOST")?)
+                    .basic_authentication(required("MSSQL_USER")?, required("MSSQL_PASSWORD")?);
+                if let Some(port) = env_parse("MSSQL_PORT")? {
+                    manager = manager.port(port);
+                }
+                if let Some(database) = env_var("MSSQL_DATABASE") {
+                    manager = manager.database(database);
+                }
+                manager
+            }
+        };
+        if let Some(max_size) = env_parse("MSSQL_POOL_MAX_SIZE")? {
+            manager = manager.max_size(max_size);
+        }
+        Ok(manager)
+    }
+
+    /// Keep track of the connection string properties tiberius doesn't expose from its config.
+    fn apply_properties(&mut self, properties: &HashMap<String, String>) {
+        self.database = properties
+            .get("database")
+            .or_else(|| properties.get("initial catalog"))
+            .or_else(|| properties.get("databasename"))
+            .cloned();
+        self.password = properties
+            .get("password")
+            .or_else(|| properties.get("pwd"))
+            .cloned();
+        self.trust_cert_ca = properties
+            .get("trustservercertificateca")
+            .map(PathBuf::from);
+        self.multi_subnet_failover = properties
+            .get("multisubnetfailover")
+            .is_some_and(|value| matches!(value.trim().to_lowercase().as_str(), "true" | "yes"));
+        self.user = properties
+            .get("uid")
+            .or_else(|| properties.get("username"))
+            .or_else(|| properties.get("user"))
+            .or_else(|| properties.get("user id"))
+            .cloned();
+        // tiberius only honours integrated security where it supports Windows authentication.
+        self.integrated_security = cfg!(all(windows, feature = "winauth"))
+            && properties
+                .get("integratedsecurity")
+                .or_else(|| properties.get("integrated security"))
+                .is_some_and(|value| {
+                    matches!(
+                        value.trim().to_lowercase().as_str(),
+                        "sspi" | "true" | "yes"
+                    )
+                });
+        self.application_name = properties
+            .get("application name")
+            .or_else(|| properties.get("applicationname"))
+            .cloned();
+        self.encryption = match properties.get("encrypt") {
+            _ if cfg!(not(any(
+                feature = "rustls",
+                feature = "native-tls",
+                feature = "vendored-openssl"
+            ))) =>
+            {
+                EncryptionLevel::NotSupported
+            }
+            Some(value) if value == "DANGER_PLAINTEXT" => EncryptionLevel::NotSupported,
+            Some(value) if matches!(value.trim().to_lowercase().as_str(), "true" | "yes") => {
+                EncryptionLevel::Required
+            }
+            _ => EncryptionLevel::Off,
+        };
+        self.trust_cert = properties
+            .get("trustservercertificate")
+            .is_some_and(|value| matches!(value.trim().to_lowercase().as_str(), "true" | "yes"));
+        self.readonly = properties
+            .get("applicationintent")
+            .is_some_and(|value| value == "ReadOnly");
     }
 
     fn new_with_tiberius_config(config: tiberius::Config) -> Self {
@@ -165,33 +865,268 @@ impl Manager {
             pool_config: Default::default(),
             runtime: None,
             hooks: Default::default(),
-            modify_tcp_stream: Box::new(|tcp_stream| tcp_stream.set_nodelay(true)),
+            modify_tcp_stream: Arc::new(|tcp_stream| tcp_stream.set_nodelay(true)),
             #[cfg(feature = "sql-browser")]
             enable_sql_browser: false,
+            diagnose_on_failure: false,
+            database: None,
+            reset_database_on_recycle: true,
+            reset_on_recycle: false,
+            on_create: None,
+            verify_encryption: false,
+            resolver: None,
+            stats: Default::default(),
+            create_error_cache: None,
+            circuit_breaker: None,
+            cached_create_error: Mutex::new(None),
+            taints: Taints::default(),
+            opened: Mutex::new(Vec::new()),
+            trust_cert_ca: None,
+            idle_ping_interval: None,
+            min_idle: 0,
+            detect_server_edition: false,
+            server_edition: OnceLock::new(),
+            hosts: Default::default(),
+            address_family: AddrFamily::Any,
+            multi_subnet_failover: false,
+            named_instance: None,
+            textsize: None,
+            query_comment: None,
+            session_tag: None,
+            catch_hook_panics: Default::default(),
+            application_role: None,
+            recycle_query: None,
+            recycle_check: None,
+            recycle_attempts: 1,
+            recycle_query_timeout: None,
+            recycle_min_idle: None,
+            max_connection_age: None,
+            tcp_keepalive: None,
+            socket_buffers: (None, None),
+            metrics_callback: None,
+            observer: None,
+            #[cfg(feature = "azure")]
+            aad_token_provider: None,
+            prefill: 0,
+            connect_retries: 0,
+            connect_backoff: Duration::from_millis(100),
+            connect_timeout: None,
+            stream_wrapper: None,
+            local_socket: None,
+            init_sql: Vec::new(),
+            lock_timeout: None,
+            query_timeout: None,
+            authentication_set: false,
+            redact_auth: false,
+            password: None,
+            user: None,
+            integrated_security: false,
+            host: None,
+            port: None,
+            instance_name: None,
+            application_name: None,
+            encryption: DEFAULT_ENCRYPTION,
+            trust_cert: false,
+            readonly: false,
+            #[cfg(feature = "testing")]
+            mock: None,
         }
     }
 
     /// Consume self, builds a pool.
-    pub fn create_pool(mut self) -> Result<Pool, error::SqlServerError> {
+    ///
+    /// Fails with [`SqlServerError::InvalidConfig`] without connecting if the configuration is
+    /// invalid, e.g. `max_size` is 0, no authentication method is set or the host is empty.
+    pub fn create_pool(mut self) -> Result<Pool, SqlServerError> {
+        self.validate()?;
+        if let Some(tag) = &self.session_tag {
+            let id = NEXT_TAGGED_POOL.fetch_add(1, Ordering::Relaxed);
+            let name = match &self.application_name {
+                Some(name) => format!("{} ({}-{})", name, tag, id),
+                None => format!("{}-{}", tag, id),
+            };
+            self = self.application_name(name);
+        }
         let config = self.pool_config;
         let runtime = self.runtime;
+        #[allow(clippy::mem_replace_with_default)]
         let hooks = replace(&mut self.hooks, Hooks::default());
-        let mut pool = Pool::builder(self).config(config);
+        let catch_hook_panics = self.catch_hook_panics.clone();
+        let idle_ping_interval = self.idle_ping_interval;
+        let min_idle = self.min_idle;
+        let metrics_callback = self.metrics_callback.take();
+        let session_settings = self.session_settings();
+        let application_role = self.application_role.take().map(Arc::new);
+        let mut pool = managed::Pool::builder(self).config(config);
         if let Some(v) = runtime {
             pool = pool.runtime(v);
         }
 
+        if let Some(role) = &application_role {
+            pool = pool.post_create(role.activate_hook());
+        }
+        if !session_settings.is_empty() {
+            let session_settings = Arc::new(session_settings);
+            pool = pool.post_create(Hook::async_fn(move |client: &mut Client, _: &Metrics| {
+                let session_settings = session_settings.clone();
+                Box::pin(async move {
+                    for sql in session_settings.iter() {
+                        let result = match client.simple_query(sql).await {
+                            Ok(stream) => stream.into_results().await.map(|_| ()),
+                            Err(e) => Err(e),
+                        };
+                        result.map_err(|e| HookError::Backend(e.into()))?;
+                    }
+                    Ok(())
+                })
+            }));
+        }
+
         for hook in hooks.post_create {
-            pool = pool.post_create(hook);
+            pool = pool.post_create(hook(&catch_hook_panics));
         }
         for hook in hooks.pre_recycle {
-            pool = pool.pre_recycle(hook);
+            pool = pool.pre_recycle(hook(&catch_hook_panics));
+        }
+        if let Some(role) = &application_role {
+            pool = pool.pre_recycle(ApplicationRole::deactivate_hook());
+            pool = pool.post_recycle(role.activate_hook());
         }
         for hook in hooks.post_recycle {
-            pool = pool.post_recycle(hook);
+            pool = pool.post_recycle(hook(&catch_hook_panics));
+        }
+
+        let pool = Pool {
+            inner: pool.build()?,
+            handles: Arc::new(()),
+        };
+        maintenance::spawn(
+            &pool,
+            Duties {
+                min_idle,
+                idle_ping: idle_ping_interval,
+                status: metrics_callback,
+            },
+        )?;
+        Ok(pool)
+    }
+
+    /// Consume self, builds a pool and opens the connections requested with
+    /// [`prefill`](Manager::prefill) before returning it.
+    ///
+    /// Connecting is async, hence this separate method: [`create_pool`](Manager::create_pool)
+    /// stays sync and opens connections lazily on the first `get()` calls. Fails if any of the
+    /// connections can't be opened.
+    pub async fn create_pool_prefilled(self) -> Result<Pool, SqlServerError> {
+        let count = self.prefill;
+        let pool = self.create_pool()?;
+        maintenance::prefill(&pool, count).await?;
+        Ok(pool)
+    }
+
+    /// Consume self, builds a pool and checks out one connection before returning it, failing
+    /// fast on an unreachable server or rejected login.
+    ///
+    /// Lets services crash at startup instead of on their first request, whereas
+    /// [`create_pool`](Manager::create_pool) only connects on the first `get()` call. The
+    /// connection is returned to the pool for reuse.
+    pub async fn create_pool_checked(self) -> Result<Pool, SqlServerError> {
+        let pool = self.create_pool()?;
+        drop(pool.get().await?);
+        Ok(pool)
+    }
+
+    /// The tiberius config connections are created with, e.g. to check what a connection string
+    /// was parsed into.
+    ///
+    /// Its `Debug` output hides passwords, but not an AAD token, see
+    /// [`debug_config`](Manager::debug_config) for output safe to log.
+    pub fn config(&self) -> &tiberius::Config {
+        &self.config
+    }
+
+    /// Summary of the [`config`](Manager::config) safe to log, with passwords and AAD tokens
+    /// redacted.
+    pub fn debug_config(&self) -> String {
+        if self.redact_auth {
+            let mut config = self.config.clone();
+            config.authentication(AuthMethod::aad_token("<HIDDEN>"));
+            format!("{:?}", config)
+        } else {
+            format!("{:?}", self.config)
         }
+    }
 
-        Ok(pool.build()?)
+    /// Cheap, synchronous checks of the configuration, catching mistakes at startup instead of
+    /// the first connection attempt.
+    fn validate(&self) -> SqlServerResult<()> {
+        #[cfg(not(any(
+            feature = "rustls",
+            feature = "native-tls",
+            feature = "vendored-openssl"
+        )))]
+        {
+            if matches!(
+                self.encryption,
+                EncryptionLevel::On | EncryptionLevel::Required
+            ) {
+                return Err(SqlServerError::Unsupported(
+                    "encryption requires one of the TLS features `rustls`, `native-tls` or \
+                     `vendored-openssl`",
+                ));
+            }
+        }
+        if let Some(instance) = &self.named_instance {
+            if instance.trim().is_empty() {
+                return Err(SqlServerError::InvalidConfig(
+                    "named_instance requires an instance name".to_string(),
+                ));
+            }
+            #[cfg(not(feature = "sql-browser"))]
+            return Err(SqlServerError::Unsupported(
+                "named instances require the `sql-browser` feature",
+            ));
+        }
+        if self.min_idle > self.pool_config.max_size {
+            return Err(SqlServerError::InvalidConfig(format!(
+                "min_idle {} exceeds max_size {}",
+                self.min_idle, self.pool_config.max_size
+            )));
+        }
+        if self.hosts.has_zero_weight() {
+            return Err(SqlServerError::InvalidConfig(
+                "weighted_hosts weights must be greater than zero".to_string(),
+            ));
+        }
+        if matches!(self.textsize, Some(size) if size < -1 || size == 0) {
+            return Err(SqlServerError::InvalidConfig(
+                "textsize must be greater than zero, or -1 for unlimited".to_string(),
+            ));
+        }
+        if let Some(path) = &self.trust_cert_ca {
+            if let Err(e) = std::fs::File::open(path) {
+                return Err(SqlServerError::InvalidConfig(format!(
+                    "trust_cert_ca `{}` is not readable: {}",
+                    path.display(),
+                    e
+                )));
+            }
+        }
+        if self.pool_config.max_size == 0 {
+            return Err(SqlServerError::InvalidConfig(
+                "max_size must be at least 1".to_string(),
+            ));
+        }
+        if !self.authentication_set {
+            return Err(SqlServerError::InvalidConfig(
+                "no authentication method set, see basic_authentication or authentication"
+                    .to_string(),
+            ));
+        }
+        if server_addr(&self.config).starts_with(':') {
+            return Err(SqlServerError::InvalidConfig("host is empty".to_string()));
+        }
+        Ok(())
     }
 
     /// Whether connected via sql-browser feature, default to `false`.
@@ -202,68 +1137,513 @@ impl Manager {
         self
     }
 
+    /// Whether to run a diagnostic sequence when creating a connection fails, defaults to `false`.
+    ///
+    /// On failure the host is resolved and a raw TCP connect is attempted, the stage which failed
+    /// is reported through [`SqlServerError::Diagnosed`]. This costs extra network round trips, but
+    /// only on the failure path.
+    pub fn diagnose_on_failure(mut self, enabled: bool) -> Self {
+        self.diagnose_on_failure = enabled;
+        self
+    }
+
     /// Server host, defaults to `localhost`.
+    ///
+    /// IPv6 literals may be given with or without brackets, e.g. `::1` or `[::1]`.
     pub fn host(mut self, host: impl ToString) -> Self {
-        self.config.host(host);
+        let host = host.to_string();
+        self.config.host(&host);
+        self.host = Some(host);
+        self
+    }
+
+    /// Spread connections over several hosts, e.g. read replicas, given as `(host, port, weight)`.
+    /// Replaces [`host`] and [`port`].
+    ///
+    /// Each new connection picks a host by weighted random selection, so a host with weight `2`
+    /// receives about twice the connections of one with weight `1`. A host failing to connect is
+    /// skipped in favour of the others for 30 seconds, and the next candidate is tried right
+    /// away. All other settings apply to every host alike, e.g. with `ApplicationIntent=ReadOnly`
+    /// each host should be a readable replica. Weights must be greater than zero. Replaces
+    /// [`hosts`], for equal weights give every host the same weight.
+    ///
+    /// [`host`]: Manager::host
+    /// [`port`]: Manager::port
+    /// [`hosts`]: Manager::hosts
+    pub fn weighted_hosts(mut self, hosts: &[(impl ToString, u16, u32)]) -> Self {
+        self.hosts = HostSet::weighted(hosts);
+        self
+    }
+
+    /// Failover hosts, e.g. the secondary listener of a cluster, tried in order after the
+    /// configured [`host`] and [`port`] until one connects.
+    ///
+    /// The host which connected last is tried first by the next connection, so a dead primary
+    /// isn't probed over and over. A host failing to connect is moved to the end of the order for
+    /// 30 seconds. If all hosts fail, the error of the last one is returned. Replaces
+    /// [`weighted_hosts`].
+    ///
+    /// [`host`]: Manager::host
+    /// [`port`]: Manager::port
+    /// [`weighted_hosts`]: Manager::weighted_hosts
+    pub fn hosts(mut self, hosts: Vec<(String, u16)>) -> Self {
+        self.hosts = HostSet::failover(hosts);
+        self
+    }
+
+    /// Resolve the server address with a custom `async fn` instead of the system resolver,
+    /// e.g. for service discovery.
+    ///
+    /// The resolver receives the address as `host:port` and the returned addresses are tried in
+    /// order until a connection is established. It is also used for addresses the server
+    /// redirects to. Not used when connecting via sql browser.
+    ///
+    /// With service discovery, e.g. Consul or Kubernetes, the host is a logical service name,
+    /// IPv6 literals arrive bracketed as in `[::1]:1433`:
+    /// ```no_run
+    /// # use std::net::SocketAddr;
+    /// # async fn lookup_service(name: &str) -> std::io::Result<SocketAddr> { unimplemented!() }
+    /// # fn main() -> deadpool_tiberius::SqlServerResult<()> {
+    /// let pool = deadpool_tiberius::Manager::new()
+    ///     .host("orders-db")
+    ///     .resolver(|addr| {
+    ///         let (service, _port) = addr.rsplit_once(':').unwrap_or((addr, ""));
+    ///         let service = service.to_string();
+    ///         Box::pin(async move { Ok(vec![lookup_service(&service).await?]) })
+    ///     })
+    ///     .create_pool()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn resolver<T>(mut self, f: T) -> Self
+    where
+        T: Fn(&str) -> BoxFuture<'static, tokio::io::Result<Vec<SocketAddr>>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.resolver = Some(Arc::new(f));
+        self
+    }
+
+    /// Wrap every connected [`TcpStream`](tokio::net::TcpStream) before the TDS handshake, e.g.
+    /// to tunnel through a proxy. Combine with [`resolver`](Manager::resolver) to connect to the
+    /// proxy instead of the server.
+    ///
+    /// Also applied to connections to the address the server redirects to, a failing wrapper
+    /// fails the [`ConnectPhase::Tcp`] phase.
+    /// ```no_run
+    /// # fn main() -> deadpool_tiberius::SqlServerResult<()> {
+    /// let pool = deadpool_tiberius::Manager::new()
+    ///     .with_stream_wrapper(|tcp| {
+    ///         Box::pin(async move {
+    ///             // e.g. perform a SOCKS handshake on `tcp` here.
+    ///             Ok(tokio::io::BufStream::new(tcp))
+    ///         })
+    ///     })
+    ///     .create_pool()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_stream_wrapper<T, S>(mut self, f: T) -> Self
+    where
+        T: Fn(tokio::net::TcpStream) -> BoxFuture<'static, tokio::io::Result<S>>
+            + Send
+            + Sync
+            + 'static,
+        S: AsyncStream + 'static,
+    {
+        self.stream_wrapper = Some(Arc::new(move |tcp| {
+            let wrapped = f(tcp);
+            Box::pin(async move { Ok(Box::new(wrapped.await?) as BoxedStream) })
+        }));
+        self
+    }
+
+    /// Connect through the Unix domain socket at `path` instead of TCP, e.g. one forwarded by a
+    /// sidecar proxy.
+    ///
+    /// The host is still sent to the server, the port and the TCP options such as
+    /// [`resolver`](Manager::resolver) and [`with_stream_wrapper`](Manager::with_stream_wrapper)
+    /// are ignored. Connections the server redirects to are made over TCP.
+    #[cfg(unix)]
+    #[cfg_attr(docsrs, doc(cfg(unix)))]
+    pub fn unix_socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.local_socket = Some(path.into());
+        self
+    }
+
+    /// Connect through the named pipe at `path` instead of TCP, e.g.
+    /// `\\.\pipe\sql\query` for a local default instance.
+    ///
+    /// The host is still sent to the server, the port and the TCP options such as
+    /// [`resolver`](Manager::resolver) and [`with_stream_wrapper`](Manager::with_stream_wrapper)
+    /// are ignored. Connections the server redirects to are made over TCP.
+    #[cfg(windows)]
+    #[cfg_attr(docsrs, doc(cfg(windows)))]
+    pub fn named_pipe(mut self, path: impl Into<PathBuf>) -> Self {
+        self.local_socket = Some(path.into());
+        self
+    }
+
+    /// Connect to all resolved addresses in parallel, taking the first to succeed, defaults to
+    /// `false`, trying them one after another.
+    ///
+    /// Speeds up failover of AlwaysOn availability group listeners spanning several subnets,
+    /// whose name resolves to an address per subnet of which only one accepts connections.
+    /// Matches the `MultiSubnetFailover=True` keyword of ado strings, which also enables it.
+    pub fn multi_subnet_failover(mut self, enabled: bool) -> Self {
+        self.multi_subnet_failover = enabled;
+        self
+    }
+
+    /// Only connect to addresses of `family`, defaults to [`AddrFamily::Any`], trying all
+    /// resolved addresses in order.
+    ///
+    /// Forcing a family avoids hangs in dual-stack networks where the other one is black-holed.
+    /// Applies to the addresses returned by the [`resolver`](Manager::resolver) too, but not when
+    /// connecting via sql browser.
+    pub fn address_family(mut self, family: AddrFamily) -> Self {
+        self.address_family = family;
         self
     }
 
     /// Server port, defaults to 1433.
     pub fn port(mut self, port: u16) -> Self {
         self.config.port(port);
+        self.port = Some(port);
         self
     }
 
     /// Database, defaults to `master`.
+    ///
+    /// This is the login-time default database (`Initial Catalog` in ADO terms), sent within the
+    /// login packet, no `USE` statement is issued after connecting. It saves a round trip and
+    /// works for logins which are not permitted to `USE` other databases.
+    /// Recycling does issue `USE [database]` to undo a borrower's database switch, unless
+    /// disabled with [`reset_database_on_recycle`].
+    ///
+    /// [`reset_database_on_recycle`]: struct.Manager.html#method.reset_database_on_recycle
     pub fn database(mut self, database: impl ToString) -> Self {
+        self.database = Some(database.to_string());
         self.config.database(database);
         self
     }
 
-    /// Simplified authentication for those using `username` and `password` as login method.
-    pub fn basic_authentication(
-        mut self,
-        username: impl ToString,
-        password: impl ToString,
-    ) -> Self {
-        self.config
-            .authentication(AuthMethod::sql_server(username, password));
+    /// Whether to switch back to the configured [`database`] when recycling a connection,
+    /// defaults to `true`.
+    ///
+    /// A borrower issuing `USE other_db`, e.g. through [`PoolExt::get_for_database`], would
+    /// otherwise hand the connection back to the pool pointing at `other_db`. When enabled,
+    /// `USE [database]` runs ahead of the liveness query. Without a configured database, the
+    /// liveness query also compares the current database to `ORIGINAL_DB_NAME()`, the login's
+    /// database, and switches back to it in a second round trip if they differ.
+    ///
+    /// [`database`]: struct.Manager.html#method.database
+    pub fn reset_database_on_recycle(mut self, enabled: bool) -> Self {
+        self.reset_database_on_recycle = enabled;
         self
     }
 
+    /// Whether to restore the session defaults when recycling a connection, defaults to `false`.
+    ///
+    /// Keeps session state of one borrower from leaking into the next: common `SET` options,
+    /// e.g. `XACT_ABORT`, `NOCOUNT` or `LOCK_TIMEOUT`, are reset, the isolation level set back to
+    /// `READ COMMITTED` and the session's temp tables dropped. Runs in the recycle batch, after
+    /// the [`reset_database_on_recycle`] reset and ahead of the [`recycle_query`].
+    ///
+    /// This approximates the `sp_reset_connection` reset of native drivers, which tiberius can't
+    /// send: `SET` options not listed above, `CONTEXT_INFO`, `sp_set_session_context` values,
+    /// open cursors and prepared statements survive, and an open transaction is not rolled back
+    /// but discards the connection as usual. The options are reset to the defaults of a fresh
+    /// tiberius connection, then the [`textsize`], [`lock_timeout`] and [`init_sql`] statements
+    /// of new connections run again, in the same batch. Options changed by an `on_create` or
+    /// `post_create` hook aren't restored.
+    ///
+    /// [`reset_database_on_recycle`]: Manager::reset_database_on_recycle
+    /// [`recycle_query`]: Manager::recycle_query
+    /// [`textsize`]: Manager::textsize
+    /// [`lock_timeout`]: Manager::lock_timeout
+    /// [`init_sql`]: Manager::init_sql
+    pub fn reset_on_recycle(mut self, enabled: bool) -> Self {
+        self.reset_on_recycle = enabled;
+        self
+    }
+
+    /// Enable or disable `TCP_NODELAY` on the connection sockets, defaults to `true`.
+    ///
+    /// Nagle's algorithm delays small writes to coalesce them, adding latency to every request
+    /// but saving packets on batch workloads sending large payloads. Applies to the connections
+    /// the server redirects to as well.
+    pub fn nodelay(mut self, enabled: bool) -> Self {
+        self.modify_tcp_stream = Arc::new(move |tcp_stream| tcp_stream.set_nodelay(enabled));
+        self
+    }
+
+    /// Enable or disable TCP keepalive on the connection sockets, defaults to the OS setting.
+    ///
+    /// Keepalive probes keep firewalls and load balancers from dropping connections idling in
+    /// the pool, which many do after a few minutes. `interval` is the idle time before the
+    /// first probe is sent, the OS default if `None`. Applied in addition to the `nodelay`
+    /// socket option, on the connections the server redirects to as well.
+    pub fn tcp_keepalive(mut self, enabled: bool, interval: Option<Duration>) -> Self {
+        self.tcp_keepalive = Some((enabled, interval));
+        self
+    }
+
+    /// Set the `SO_SNDBUF` and `SO_RCVBUF` sizes of the connection sockets in bytes, `None`
+    /// keeps the OS default.
+    ///
+    /// Mainly helps streaming large results over high-latency, high-bandwidth links, where the
+    /// default buffers can't hold a round trip worth of data. The OS may clamp or round the
+    /// requested sizes, Linux e.g. doubles them and caps them at `net.core.wmem_max` and
+    /// `net.core.rmem_max`. Applied along with the `nodelay` and keepalive socket options, on
+    /// the connections the server redirects to as well.
+    pub fn socket_buffers(mut self, send: Option<usize>, recv: Option<usize>) -> Self {
+        self.socket_buffers = (send, recv);
+        self
+    }
+
+    /// Discard connections older than `age` instead of recycling them, defaults to no limit.
+    ///
+    /// Checked before the liveness query, so an aged connection is replaced without a round
+    /// trip. Trades a bit of connection churn for resilience against sockets silently dropped by
+    /// the server or a load balancer after some time.
+    pub fn max_connection_age(mut self, age: Duration) -> Self {
+        self.max_connection_age = Some(age);
+        self
+    }
+
+    /// Run `sql` when recycling a connection, e.g. a procedure resetting session state, defaults
+    /// to none.
+    ///
+    /// `sql` runs in one batch with the `USE [database]` and [`reset_on_recycle`] resets and the
+    /// `SELECT @@TRANCOUNT` liveness probe, which discards connections with a leaked transaction.
+    /// Any error fails the recycle with [`RecycleError::Message`], discarding the connection.
+    /// Combine it with [`recycle_timeout`] to bound a slow probe.
+    ///
+    /// [`reset_on_recycle`]: Manager::reset_on_recycle
+    /// [`recycle_timeout`]: Manager::recycle_timeout
+    pub fn recycle_query(mut self, sql: impl ToString) -> Self {
+        self.recycle_query = Some(Arc::from(sql.to_string()));
+        self
+    }
+
+    /// Run the recycle probe up to `attempts` times before discarding the connection, defaults
+    /// to 1, 0 is treated as 1.
+    ///
+    /// Only server errors below severity 20 are retried, after a short pause, e.g. a cancelled
+    /// query, a lock timeout or a deadlock. The connection is still usable after those, while
+    /// I/O and protocol errors, as well as severe server errors, leave it broken and discard it
+    /// right away. Has no effect with a [`recycle_check`](Manager::recycle_check).
+    pub fn recycle_retry(mut self, attempts: u32) -> Self {
+        self.recycle_attempts = attempts.max(1);
+        self
+    }
+
+    /// Give up on the recycle probe after `timeout`, defaults to no limit.
+    ///
+    /// Bounds the liveness probe, along with the [`recycle_query`] and the retries of
+    /// [`recycle_retry`], so a wedged connection is discarded promptly instead of blocking the
+    /// checkout. Expiry fails the recycle with [`RecycleError::StaticMessage`], counted in
+    /// [`lifetime_stats`] and logged like any failed recycle. Keep it below [`recycle_timeout`],
+    /// which also bounds the probe and would otherwise fire first, dropping the connection
+    /// without a recycle error. Has no effect with a [`recycle_check`](Manager::recycle_check).
+    ///
+    /// [`recycle_query`]: Manager::recycle_query
+    /// [`recycle_retry`]: Manager::recycle_retry
+    /// [`recycle_timeout`]: Manager::recycle_timeout
+    pub fn recycle_query_timeout(mut self, timeout: Duration) -> Self {
+        self.recycle_query_timeout = Some(timeout);
+        self
+    }
+
+    /// Skip the recycle probe for connections checked out within the last `idle`, defaults to
+    /// probing on every checkout.
+    ///
+    /// Saves a round trip per checkout on busy pools, where a connection used moments ago is
+    /// almost certainly still alive. The recycle batch is skipped as a whole, so within the
+    /// window the [`session`](Manager::reset_on_recycle) reset and the [`recycle_query`] don't
+    /// run either. Connections switched to another database through
+    /// [`ClientExt::use_database`] or [`PoolExt::get_for_database`] always run the full batch,
+    /// and those dropped with an unfinished [`TransactionGuard`] are always discarded, when
+    /// called on the pooled connection. [`max_connection_age`] still applies. Has no effect with
+    /// a [`recycle_check`](Manager::recycle_check).
+    ///
+    /// [`recycle_query`]: Manager::recycle_query
+    /// [`max_connection_age`]: Manager::max_connection_age
+    pub fn recycle_min_idle(mut self, idle: Duration) -> Self {
+        self.recycle_min_idle = Some(idle);
+        self
+    }
+
+    /// Simplified authentication for those using `username` and `password` as login method.
+    pub fn basic_authentication(self, username: impl ToString, password: impl ToString) -> Self {
+        let (username, password) = (username.to_string(), password.to_string());
+        let mut manager = self.authentication(AuthMethod::sql_server(&username, &password));
+        manager.user = Some(username);
+        manager.password = Some(password);
+        manager
+    }
+
     /// Set [`tiberius::AuthMethod`] as authentication method.
     pub fn authentication(mut self, authentication: AuthMethod) -> Self {
+        self.redact_auth = matches!(authentication, AuthMethod::AADToken(_));
+        // The Windows variants only exist on Windows.
+        self.integrated_security = !matches!(
+            authentication,
+            AuthMethod::SqlServer(_) | AuthMethod::AADToken(_) | AuthMethod::None
+        );
+        self.user = None;
+        self.password = None;
         self.config.authentication(authentication);
+        self.authentication_set = true;
+        self
+    }
+
+    /// Authenticate with an Azure Active Directory access token, see [`AuthMethod::aad_token`].
+    ///
+    /// The token is fixed and expires, usually after an hour, see
+    /// `aad_token_provider` of the `azure` feature for long-lived pools.
+    pub fn aad_token_authentication(self, token: impl ToString) -> Self {
+        self.authentication(AuthMethod::aad_token(token))
+    }
+
+    /// Authenticate with Azure Active Directory access tokens fetched by `provider`, called for
+    /// every new connection so expired tokens are replaced. Takes precedence over any other
+    /// authentication method, an error of `provider` fails the connection creation with
+    /// [`SqlServerError::AadToken`].
+    #[cfg(feature = "azure")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "azure")))]
+    pub fn aad_token_provider<T>(mut self, provider: T) -> Self
+    where
+        T: Fn() -> BoxFuture<'static, Result<String, Box<dyn std::error::Error + Send + Sync>>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.aad_token_provider = Some(Arc::new(provider));
+        self.authentication_set = true;
+        self
+    }
+
+    /// Declare the application's workload as read-only (`ApplicationIntent=ReadOnly`), defaults to
+    /// read-write.
+    ///
+    /// An AlwaysOn availability group listener routes read-only connections to a readable
+    /// secondary replica, the intent is kept when following the redirect. Up to 5 chained
+    /// redirects are followed, more fail with [`SqlServerError::TooManyRedirects`].
+    pub fn application_intent_readonly(mut self) -> Self {
+        self.config.readonly(true);
+        self.readonly = true;
         self
     }
 
     /// See [`tiberius::Config::trust_cert`]
     pub fn trust_cert(mut self) -> Self {
         self.config.trust_cert();
+        self.trust_cert = true;
         self
     }
 
     /// Set [`tiberius::EncryptionLevel`] as enctryption method.
     pub fn encryption(mut self, encryption: EncryptionLevel) -> Self {
         self.config.encryption(encryption);
+        self.encryption = encryption;
+        self
+    }
+
+    /// Require an encrypted connection and verify it after the handshake.
+    ///
+    /// Sets [`EncryptionLevel::Required`], then checks with the server that the established
+    /// connection is actually encrypted, rejecting it with [`SqlServerError::EncryptionDowngrade`]
+    /// otherwise. This detects an encryption downgrade forced during prelogin negotiation, at the
+    /// cost of one extra query per created connection.
+    pub fn encryption_required(mut self) -> Self {
+        self.config.encryption(EncryptionLevel::Required);
+        self.encryption = EncryptionLevel::Required;
+        self.verify_encryption = true;
+        self
+    }
+
+    /// Encrypt the connection if the server supports it, otherwise silently fall back to an
+    /// unencrypted connection ([`EncryptionLevel::On`]).
+    ///
+    /// Whether encryption ended up active can be checked with [`ClientExt::is_encrypted`].
+    /// Note opportunistic encryption offers no protection against a man-in-the-middle, who can
+    /// simply claim the server doesn't support encryption. Use [`encryption_required`] where
+    /// that matters.
+    ///
+    /// [`encryption_required`]: struct.Manager.html#method.encryption_required
+    pub fn encryption_optional(mut self) -> Self {
+        self.config.encryption(EncryptionLevel::On);
+        self.encryption = EncryptionLevel::On;
+        self.verify_encryption = false;
         self
     }
 
     /// See [`tiberius::Config::trust_cert_ca`]
+    ///
+    /// [`create_pool`] fails with [`SqlServerError::InvalidConfig`] if the file is not readable.
+    ///
+    /// [`create_pool`]: struct.Manager.html#method.create_pool
     pub fn trust_cert_ca(mut self, path: impl ToString) -> Self {
+        self.trust_cert_ca = Some(PathBuf::from(path.to_string()));
         self.config.trust_cert_ca(path);
         self
     }
 
     /// Instance name defined in `Sql Browser`, defaults to None.
+    ///
+    /// Only used when connecting through the sql browser, see
+    /// [`named_instance`](Manager::named_instance) which sets both.
     pub fn instance_name(mut self, name: impl ToString) -> Self {
-        self.config.instance_name(name);
+        let name = name.to_string();
+        self.config.instance_name(&name);
+        self.instance_name = Some(name);
         self
     }
 
+    /// Connect to the named `instance` on `host`, e.g. `SQLEXPRESS`, looking up its port with the
+    /// sql browser (UDP port 1434) on the host.
+    ///
+    /// Sets the host and the instance name and enables the sql browser, which requires the
+    /// `sql-browser` feature: without it [`create_pool`](Manager::create_pool) fails with
+    /// [`SqlServerError::Unsupported`]. An empty instance name fails with
+    /// [`SqlServerError::InvalidConfig`].
+    pub fn named_instance(self, host: impl ToString, instance: impl ToString) -> Self {
+        let mut manager = self.host(host).instance_name(instance);
+        manager.named_instance = manager.instance_name.clone();
+        #[cfg(feature = "sql-browser")]
+        {
+            manager.enable_sql_browser = true;
+        }
+        manager
+    }
+
     /// See [`tiberius::Config::application_name`]
     pub fn application_name(mut self, name: impl ToString) -> Self {
-        self.config.application_name(name);
+        let name = name.to_string();
+        self.config.application_name(&name);
+        self.application_name = Some(name);
+        self
+    }
+
+    /// Tag the connections of every pool built from this manager with `tag` and a number unique
+    /// within the process, e.g. `orders-3`, to tell pools apart on the server.
+    ///
+    /// The tag is appended to the [`application_name`](Manager::application_name) as in
+    /// `my-app (orders-3)`, or used as application name if there is none, replacing the one of a
+    /// config passed to [`from_config`](Manager::from_config). It shows up as `program_name`,
+    /// e.g. to find the sessions of a pool leaking connections:
+    /// ```sql
+    /// SELECT program_name, COUNT(*) AS sessions, MAX(last_request_end_time) AS last_used
+    /// FROM sys.dm_exec_sessions
+    /// WHERE program_name LIKE '%(orders-%'
+    /// GROUP BY program_name
+    /// ```
+    pub fn session_tag(mut self, tag: impl ToString) -> Self {
+        self.session_tag = Some(tag.to_string());
         self
     }
 
@@ -273,24 +1653,397 @@ impl Manager {
         self
     }
 
+    /// Runtime driving the pool timeouts, kept by the timeout setters.
+    ///
+    /// Without it, setting a timeout picks tokio, or async-std with the `runtime-async-std`
+    /// feature, and no runtime is set as long as no timeout is. Only affects the timers, see the
+    /// [crate docs](crate#runtimes) for what else needs tokio.
+    pub fn runtime(mut self, runtime: Runtime) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
     /// Set timeout for when waiting for a connection object to become available.
     pub fn wait_timeout(mut self, value: Duration) -> Self {
         self.pool_config.timeouts.wait = Some(value);
-        self.set_runtime(Runtime::Tokio1);
+        self.default_runtime();
         self
     }
 
     /// Set timeout for when creating a new connection object.
     pub fn create_timeout(mut self, value: Duration) -> Self {
         self.pool_config.timeouts.create = Some(value);
-        self.set_runtime(Runtime::Tokio1);
+        self.default_runtime();
+        self
+    }
+
+    /// Set the wait, create and recycle timeouts at once, replacing any set before, e.g. to copy
+    /// them from another pool via [`Pool::timeouts`](deadpool::managed::Pool::timeouts).
+    ///
+    /// The timeouts left `None` are disabled, see [`wait_timeout`], [`create_timeout`] and
+    /// [`recycle_timeout`] for what they bound.
+    ///
+    /// [`wait_timeout`]: Manager::wait_timeout
+    /// [`create_timeout`]: Manager::create_timeout
+    /// [`recycle_timeout`]: Manager::recycle_timeout
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.pool_config.timeouts = timeouts;
+        if timeouts.wait.is_some() || timeouts.create.is_some() || timeouts.recycle.is_some() {
+            self.default_runtime();
+        }
+        self
+    }
+
+    /// Set timeout for establishing the TCP connection only, per resolved address, defaults to
+    /// the OS limit.
+    ///
+    /// Unlike [`create_timeout`], which bounds the whole creation including TLS and login, this
+    /// gives up on a black-holed address quickly and moves on to the next one, failing with
+    /// [`SqlServerError::Timeout`] of kind [`TimeoutKind::Connect`] in [`ConnectPhase::Tcp`].
+    ///
+    /// [`create_timeout`]: Manager::create_timeout
+    pub fn connect_timeout(mut self, value: Duration) -> Self {
+        self.connect_timeout = Some(value);
         self
     }
 
     /// Set timeout for when recycling a connection object.
+    ///
+    /// Bounds the recycle checks of the manager, i.e. the [`recycle_check`] or the liveness probe,
+    /// but not the `pre_recycle` and `post_recycle` hooks. A connection running out of time is
+    /// dropped silently and the checkout moves on to the next one, see
+    /// [`recycle_query_timeout`](Manager::recycle_query_timeout) to bound the liveness probe
+    /// alone.
+    ///
+    /// [`recycle_check`]: Manager::recycle_check
     pub fn recycle_timeout(mut self, value: Duration) -> Self {
         self.pool_config.timeouts.recycle = Some(value);
-        self.set_runtime(Runtime::Tokio1);
+        self.default_runtime();
+        self
+    }
+
+    /// Replace the validation of connections on recycle with an `async fn`, replacing any
+    /// previous one.
+    ///
+    /// Unlike `pre_recycle` hooks, which run alongside it, this replaces the built-in check: the
+    /// `SELECT @@TRANCOUNT` liveness probe, the [`reset_database_on_recycle`] and
+    /// [`reset_on_recycle`] resets and the [`recycle_query`]. An `Ok` hands the connection out
+    /// again, an error discards it. [`max_connection_age`] still applies.
+    ///
+    /// The override thus disables the leaked transaction check and the database reset:
+    /// connections left with an open transaction or switched to another database are handed
+    /// out as they are, unless `f` checks for it. Only a [`TransactionGuard`] begun on a pooled
+    /// connection and dropped unfinished still discards it. Skipping validation, e.g. by always
+    /// returning `Ok`, saves a round trip per checkout, but broken connections then only
+    /// surface on the next query.
+    /// ```no_run
+    /// # fn main() -> deadpool_tiberius::SqlServerResult<()> {
+    /// use deadpool_tiberius::deadpool::managed::RecycleError;
+    ///
+    /// let pool = deadpool_tiberius::Manager::new()
+    ///     .recycle_check(|client, _metrics| {
+    ///         Box::pin(async move {
+    ///             match client.simple_query("SELECT 1").await {
+    ///                 Ok(stream) => stream.into_results().await.map(|_| ()),
+    ///                 Err(e) => Err(e),
+    ///             }
+    ///             .map_err(|e| RecycleError::Backend(e.into()))
+    ///         })
+    ///     })
+    ///     .create_pool()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`reset_database_on_recycle`]: Manager::reset_database_on_recycle
+    /// [`reset_on_recycle`]: Manager::reset_on_recycle
+    /// [`recycle_query`]: Manager::recycle_query
+    /// [`max_connection_age`]: Manager::max_connection_age
+    pub fn recycle_check<T>(mut self, f: T) -> Self
+    where
+        T: for<'a> Fn(&'a mut Client, &'a Metrics) -> BoxFuture<'a, RecycleResult<SqlServerError>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.recycle_check = Some(Arc::new(f));
+        self
+    }
+
+    /// Set an `async fn` initializing each newly created connection, replacing any previous one.
+    ///
+    /// It runs right after login, after the socket options of `modify_tcp_stream` (`nodelay`)
+    /// have been applied and before any `post_create` hook. An error returned from it aborts
+    /// the connection creation and is handed to the caller of `pool.get()` as is, rather than
+    /// wrapped as a hook failure.
+    /// ```no_run
+    /// # fn main() -> deadpool_tiberius::SqlServerResult<()> {
+    /// let pool = deadpool_tiberius::Manager::new()
+    ///     .on_create(|client| {
+    ///         Box::pin(async move {
+    ///             client.simple_query("SET ARITHABORT ON").await?;
+    ///             Ok(())
+    ///         })
+    ///     })
+    ///     .create_pool()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_create<T>(mut self, f: T) -> Self
+    where
+        T: for<'a> Fn(&'a mut Client) -> BoxFuture<'a, SqlServerResult<()>> + Send + Sync + 'static,
+    {
+        self.on_create = Some(Arc::new(f));
+        self
+    }
+
+    /// Detect the [`ServerEdition`] on the first connection created, defaults to `false`.
+    ///
+    /// The result is cached and available through [`Manager::server_edition`], e.g.
+    /// `pool.manager().server_edition()`, to adapt to edition specific behaviour such as Azure SQL
+    /// Database not supporting `USE`.
+    pub fn detect_server_edition(mut self) -> Self {
+        self.detect_server_edition = true;
+        self
+    }
+
+    /// The edition detected when [`detect_server_edition`] is enabled, `None` until the first
+    /// connection has been created.
+    ///
+    /// [`detect_server_edition`]: struct.Manager.html#method.detect_server_edition
+    pub fn server_edition(&self) -> Option<ServerEdition> {
+        self.server_edition.get().copied()
+    }
+
+    /// Activate the application role `name` with `password` through `sp_setapprole` on every new
+    /// connection, defaults to none.
+    ///
+    /// An active application role prevents resetting the session, so it is deactivated with
+    /// `sp_unsetapprole` before each recycle and activated again afterwards, handing every borrower
+    /// a freshly activated role. The cookie required for deactivating is kept in the session
+    /// context, which needs SQL Server 2016 or later. Activation runs before [`textsize`] and the
+    /// `post_create`/`post_recycle` hooks, deactivation after the `pre_recycle` hooks.
+    ///
+    /// The password is sent as is, the ODBC `@encrypt = 'odbc'` obfuscation isn't available
+    /// through tiberius, so keep the connection encrypted.
+    ///
+    /// [`textsize`]: Manager::textsize
+    pub fn application_role(mut self, name: impl ToString, password: impl ToString) -> Self {
+        self.application_role = Some(ApplicationRole::new(name.to_string(), password.to_string()));
+        self
+    }
+
+    /// Tag queries with a comment, e.g. `app: orders-service`, to attribute them in Query Store,
+    /// which captures the query text including comments. Defaults to no comment.
+    ///
+    /// tiberius' [`Client`] can't be intercepted, so the comment is applied to sql passed through
+    /// [`Manager::commented`], e.g. `conn.query(pool.manager().commented(sql), params)`. For a
+    /// per-call comment see [`ClientExt::query_commented`].
+    pub fn query_comment(mut self, comment: impl ToString) -> Self {
+        self.query_comment = Some(comment.to_string());
+        self
+    }
+
+    /// `sql` prefixed with the [`query_comment`], unchanged if none is set. Parameter
+    /// placeholders are unaffected.
+    ///
+    /// [`query_comment`]: Manager::query_comment
+    pub fn commented<'a>(&self, sql: &'a str) -> Cow<'a, str> {
+        match &self.query_comment {
+            Some(comment) => Cow::Owned(comment_sql(comment, sql)),
+            None => Cow::Borrowed(sql),
+        }
+    }
+
+    /// Retry connecting up to `retries` times when creating a connection fails, defaults to 0.
+    ///
+    /// Only transient failures are retried, i.e. those
+    /// [`is_connection`](SqlServerError::is_connection) is true for, such as I/O errors or
+    /// connect timeouts. A rejected login or server certificate, an unavailable database or an
+    /// invalid config fail right away.
+    ///
+    /// Attempts are spaced by an exponential backoff, see
+    /// [`connect_backoff`](Manager::connect_backoff), and the error of the last attempt is
+    /// returned. All attempts count against [`create_timeout`](Manager::create_timeout).
+    pub fn connect_retries(mut self, retries: u32) -> Self {
+        self.connect_retries = retries;
+        self
+    }
+
+    /// Delay before the first connect retry, doubled for every further retry and randomized
+    /// by up to -50% as jitter, defaults to 100ms.
+    pub fn connect_backoff(mut self, base: Duration) -> Self {
+        self.connect_backoff = base;
+        self
+    }
+
+    /// Number of connections [`create_pool_prefilled`](Manager::create_pool_prefilled) opens
+    /// before returning the pool, so the first requests after a cold start don't pay for
+    /// connecting. Capped at [`max_size`](Manager::max_size), ignored by
+    /// [`create_pool`](Manager::create_pool).
+    pub fn prefill(mut self, count: usize) -> Self {
+        self.prefill = count;
+        self
+    }
+
+    /// Keep at least `n` idle connections open, defaults to 0.
+    ///
+    /// A background task checks every second and opens the missing connections, so bursts don't
+    /// pay for connecting. The idle connections stay available while connecting, the task
+    /// leaves the slots to requests waiting for a connection and stops once the pool is closed
+    /// or dropped. Must not exceed [`max_size`](Manager::max_size), and [`create_pool`] must be
+    /// called within a tokio runtime when set.
+    ///
+    /// [`create_pool`]: struct.Manager.html#method.create_pool
+    pub fn min_idle(mut self, n: usize) -> Self {
+        self.min_idle = n;
+        self
+    }
+
+    /// Ping idle connections every `interval` from a background task, discarding the ones which
+    /// fail, defaults to no pinging.
+    ///
+    /// Keeps idle connections from being dropped server-side or by a firewall during quiet
+    /// periods. The idle connections are checked out one at a time, without waiting, and
+    /// returned right away, so the recycle probe pings them and real traffic isn't competed
+    /// with. The task stops once the pool is closed with [`deadpool::managed::Pool::close`]
+    /// or dropped. [`create_pool`] must be called within a tokio runtime when set.
+    ///
+    /// [`create_pool`]: struct.Manager.html#method.create_pool
+    pub fn idle_ping_interval(mut self, interval: Duration) -> Self {
+        self.idle_ping_interval = Some(interval);
+        self
+    }
+
+    /// `SET TEXTSIZE` on every new connection, in bytes, `-1` meaning unlimited. Defaults to the
+    /// login's setting.
+    ///
+    /// `TEXTSIZE` caps the size of `varchar(max)`, `nvarchar(max)`, `varbinary(max)`, `text` and
+    /// `image` values returned by `SELECT`, anything longer is silently truncated. A small server
+    /// default is a classic cause of truncated large column reads. Runs as a `post_create` hook
+    /// before the ones attached with [`post_create_sync`]/[`post_create_async`].
+    ///
+    /// [`post_create_sync`]: Manager::post_create_sync
+    /// [`post_create_async`]: Manager::post_create_async
+    pub fn textsize(mut self, size: i32) -> Self {
+        self.textsize = Some(size);
+        self
+    }
+
+    /// Statements run in order on every new connection, e.g. `SET ARITHABORT ON` or
+    /// `SET LOCK_TIMEOUT 5000`, replacing any previously set.
+    ///
+    /// A failing statement fails the connection creation, so the connection isn't pooled. Runs as
+    /// a `post_create` hook after [`textsize`](Manager::textsize) and before the ones attached
+    /// with [`post_create_sync`]/[`post_create_async`].
+    ///
+    /// [`post_create_sync`]: Manager::post_create_sync
+    /// [`post_create_async`]: Manager::post_create_async
+    pub fn init_sql(mut self, statements: Vec<String>) -> Self {
+        self.init_sql = statements;
+        self
+    }
+
+    /// Run `SET LOCK_TIMEOUT ms` on every new connection, making statements waiting longer than
+    /// `ms` milliseconds for a lock fail with error 1222, defaults to waiting indefinitely.
+    ///
+    /// Runs right before the [`init_sql`](Manager::init_sql) statements, which may override it.
+    pub fn lock_timeout(mut self, ms: u32) -> Self {
+        self.lock_timeout = Some(ms);
+        self
+    }
+
+    /// Bound the queries run through [`PoolQueryExt`] to `timeout` on the client side, failing
+    /// with [`SqlServerError::Timeout`] of kind [`TimeoutKind::Query`], defaults to none.
+    ///
+    /// tiberius can't cancel a running statement, so on expiry the connection is closed instead
+    /// of returned to the pool. The server stops the statement once it notices, rolling back
+    /// its open transaction, but it may complete the statement first. Prefer server-side bounds
+    /// such as [`lock_timeout`](Manager::lock_timeout) where they suffice.
+    pub fn query_timeout(mut self, timeout: Duration) -> Self {
+        self.query_timeout = Some(timeout);
+        self
+    }
+
+    /// Report the pool's [`Status`] to `f` every `interval` from a background task, e.g. to export
+    /// it as gauges, defaults to no reporting.
+    ///
+    /// The first report is made right away. The task stops once the pool is closed with
+    /// [`deadpool::managed::Pool::close`] or dropped. [`create_pool`] must be called within a
+    /// tokio runtime when set. For an on demand snapshot, call `pool.status()`:
+    /// ```no_run
+    /// # async fn f(pool: deadpool_tiberius::Pool) {
+    /// let status = pool.status();
+    /// println!("{} of {} connections idle, {} waiting", status.available, status.size, status.waiting);
+    /// # }
+    /// ```
+    ///
+    /// [`create_pool`]: struct.Manager.html#method.create_pool
+    pub fn with_metrics_callback<T>(mut self, interval: Duration, f: T) -> Self
+    where
+        T: Fn(Status) + Send + Sync + 'static,
+    {
+        self.metrics_callback = Some((interval, Arc::new(f)));
+        self
+    }
+
+    /// Report connection creation, recycling, checkouts and returns to `observer`, e.g. to
+    /// export them with a metrics crate, defaults to none.
+    ///
+    /// Creates and recycles are reported for every connection. Checkouts and returns only for
+    /// connections checked out with [`PoolExt::get_observed`], as `pool.get()` hands out
+    /// deadpool's objects as is. Creates failing fast on an open [`circuit_breaker`] or a
+    /// [`create_error_cache`] aren't reported either.
+    ///
+    /// [`circuit_breaker`]: Manager::circuit_breaker
+    /// [`create_error_cache`]: Manager::create_error_cache
+    pub fn observer(mut self, observer: Arc<dyn PoolObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Cache the error of a failed connection creation for `window`, defaults to no caching.
+    ///
+    /// Within the window, creating a connection fails immediately with
+    /// [`SqlServerError::CreateErrorCached`] instead of attempting a fresh, likely failing,
+    /// connection. This dampens retry storms during an outage, at the cost of detecting the
+    /// recovery up to `window` later. The next attempt after the window connects for real.
+    pub fn create_error_cache(mut self, window: Duration) -> Self {
+        self.create_error_cache = Some(window);
+        self
+    }
+
+    /// Fail connection creation fast once it failed `failures` times in a row within `window`,
+    /// defaults to off.
+    ///
+    /// The open breaker fails `pool.get()` immediately with [`SqlServerError::CircuitOpen`]
+    /// whenever a new connection is needed, instead of piling up callers waiting on connects
+    /// timing out while the server is down. Idle connections are still handed out. After
+    /// `cooldown` a single creation is let through to probe the server, closing the breaker on
+    /// success and opening it for another `cooldown` on failure. `failures` of 0 is treated as
+    /// 1.
+    ///
+    /// Unlike [`create_error_cache`](Manager::create_error_cache), which fails fast after any
+    /// failure, this tolerates occasional failures.
+    pub fn circuit_breaker(mut self, failures: u32, window: Duration, cooldown: Duration) -> Self {
+        self.circuit_breaker = Some(CircuitBreaker::new(failures, window, cooldown));
+        self
+    }
+
+    /// Whether panics of hooks, including [`on_create`] and [`recycle_check`], are caught,
+    /// defaults to `false`.
+    ///
+    /// When enabled, a panicking hook fails with [`SqlServerError::HookPanicked`] carrying the
+    /// panic message, instead of unwinding into the caller of `pool.get()`. The failure is handled
+    /// like any hook error: a new connection is dropped and a recycled one discarded, so a
+    /// connection left in an inconsistent state by the panic isn't reused. Hooks are run under
+    /// [`AssertUnwindSafe`], state they share beyond the connection must tolerate a panic midway,
+    /// e.g. a `std::sync::Mutex` they hold gets poisoned. Has no effect with `panic = "abort"`.
+    ///
+    /// [`on_create`]: Manager::on_create
+    /// [`recycle_check`]: Manager::recycle_check
+    pub fn catch_hook_panics(self, enabled: bool) -> Self {
+        self.catch_hook_panics.store(enabled, Ordering::Relaxed);
         self
     }
 
@@ -298,9 +2051,10 @@ impl Manager {
     /// The hook will be called each time before a connection [`deadpool::managed::Object`] is recycled.
     pub fn pre_recycle_sync<T>(mut self, hook: T) -> Self
     where
-        T: Fn(&mut Client, &Metrics) -> HookResult<Error> + Sync + Send + 'static,
+        T: Fn(&mut Client, &Metrics) -> HookResult<SqlServerError> + Sync + Send + 'static,
     {
-        self.hooks.pre_recycle.push(Hook::sync_fn(hook));
+        let hook = hook::sync_factory(hook);
+        self.hooks.pre_recycle.push(hook);
         self
     }
 
@@ -308,9 +2062,13 @@ impl Manager {
     /// The hook will be called each time before a connection [`deadpool::managed::Object`] is recycled.
     pub fn pre_recycle_async<T>(mut self, hook: T) -> Self
     where
-        T: for<'a> Fn(&'a mut Client, &'a Metrics) -> HookFuture<'a, Error> + Sync + Send + 'static,
+        T: for<'a> Fn(&'a mut Client, &'a Metrics) -> HookFuture<'a, SqlServerError>
+            + Sync
+            + Send
+            + 'static,
     {
-        self.hooks.pre_recycle.push(Hook::async_fn(hook));
+        let hook = hook::async_factory(hook);
+        self.hooks.pre_recycle.push(hook);
         self
     }
 
@@ -318,9 +2076,10 @@ impl Manager {
     /// The hook will be called each time af after a connection [`deadpool::managed::Object`] is recycled.
     pub fn post_recycle_sync<T>(mut self, hook: T) -> Self
     where
-        T: Fn(&mut Client, &Metrics) -> HookResult<Error> + Sync + Send + 'static,
+        T: Fn(&mut Client, &Metrics) -> HookResult<SqlServerError> + Sync + Send + 'static,
     {
-        self.hooks.post_recycle.push(Hook::sync_fn(hook));
+        let hook = hook::sync_factory(hook);
+        self.hooks.post_recycle.push(hook);
         self
     }
 
@@ -328,19 +2087,38 @@ impl Manager {
     /// The hook will be called each time after a connection [`deadpool::managed::Object`] is recycled.
     pub fn post_recycle_async<T>(mut self, hook: T) -> Self
     where
-        T: for<'a> Fn(&'a mut Client, &'a Metrics) -> HookFuture<'a, Error> + Sync + Send + 'static,
+        T: for<'a> Fn(&'a mut Client, &'a Metrics) -> HookFuture<'a, SqlServerError>
+            + Sync
+            + Send
+            + 'static,
     {
-        self.hooks.post_recycle.push(Hook::async_fn(hook));
+        let hook = hook::async_factory(hook);
+        self.hooks.post_recycle.push(hook);
         self
     }
 
+    /// Observe the [`Metrics`] of each connection about to be recycled, e.g. to record reuse
+    /// counts and ages. Registered as a [`pre_recycle_sync`] hook which never fails.
+    ///
+    /// [`pre_recycle_sync`]: Manager::pre_recycle_sync
+    pub fn on_recycle_metrics<T>(self, f: T) -> Self
+    where
+        T: Fn(&Metrics) + Sync + Send + 'static,
+    {
+        self.pre_recycle_sync(move |_client, metrics| {
+            f(metrics);
+            Ok(())
+        })
+    }
+
     /// Attach a `sync fn` as hook to connection pool.
     /// The hook will be called each time after a connection [`deadpool::managed::Object`] is created.
     pub fn post_create_sync<T>(mut self, hook: T) -> Self
     where
-        T: Fn(&mut Client, &Metrics) -> HookResult<Error> + Sync + Send + 'static,
+        T: Fn(&mut Client, &Metrics) -> HookResult<SqlServerError> + Sync + Send + 'static,
     {
-        self.hooks.post_create.push(Hook::sync_fn(hook));
+        let hook = hook::sync_factory(hook);
+        self.hooks.post_create.push(hook);
         self
     }
 
@@ -348,29 +2126,463 @@ impl Manager {
     /// The hook will be called each time after a connection [`deadpool::managed::Object`] is created.
     pub fn post_create_async<T>(mut self, hook: T) -> Self
     where
-        T: for<'a> Fn(&'a mut Client, &'a Metrics) -> HookFuture<'a, Error> + Sync + Send + 'static,
+        T: for<'a> Fn(&'a mut Client, &'a Metrics) -> HookFuture<'a, SqlServerError>
+            + Sync
+            + Send
+            + 'static,
     {
-        self.hooks.post_create.push(Hook::async_fn(hook));
+        let hook = hook::async_factory(hook);
+        self.hooks.post_create.push(hook);
         self
     }
 
-    fn set_runtime(&mut self, value: Runtime) {
-        self.runtime = Some(value);
+    async fn create_client(&self) -> Result<Client, SqlServerError> {
+        let started = Instant::now();
+        let mut client = self
+            .connect()
+            .await
+            .map_err(|e| e.database_unavailable(self.database.clone()))?;
+        self.init_client(&mut client)
+            .await
+            .map_err(|e| SqlServerError::connect_failed(ConnectPhase::Init, e))?;
+        self.stats.created(started.elapsed());
+        if let Some(observer) = &self.observer {
+            observer.on_create(started.elapsed());
+        }
+        Ok(client)
+    }
+
+    async fn init_client(&self, client: &mut Client) -> Result<(), SqlServerError> {
+        if self.verify_encryption && !client.is_encrypted().await? {
+            return Err(SqlServerError::EncryptionDowngrade);
+        }
+        if self.detect_server_edition && self.server_edition.get().is_none() {
+            let edition = client.server_edition().await?;
+            let _ = self.server_edition.set(edition);
+        }
+        if let Some(on_create) = &self.on_create {
+            if self.catch_hook_panics.load(Ordering::Relaxed) {
+                AssertUnwindSafe(async { on_create(client).await })
+                    .catch_unwind()
+                    .await
+                    .map_err(hook::panicked)??;
+            } else {
+                on_create(client).await?;
+            }
+        }
+        Ok(())
     }
+
+    async fn connect(&self) -> Result<Client, SqlServerError> {
+        let mut attempt = 0;
+        loop {
+            match self.connect_once().await {
+                Err(e) if e.is_connection() && attempt < self.connect_retries => {
+                    // Exponential backoff with jitter, so clients don't reconnect in lockstep.
+                    let backoff = self.connect_backoff.saturating_mul(1 << attempt.min(16));
+                    tokio::time::sleep(backoff.mul_f64(0.5 + fastrand::f64() / 2.0)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn connect_once(&self) -> Result<Client, SqlServerError> {
+        #[allow(unused_mut)]
+        let mut config = self.config.clone();
+        #[cfg(feature = "azure")]
+        if let Some(provider) = &self.aad_token_provider {
+            let token = provider().await.map_err(SqlServerError::AadToken)?;
+            config.authentication(AuthMethod::aad_token(token));
+        }
+        if self.hosts.is_empty() {
+            return self.connect_diagnosed(&config).await;
+        }
+        let mut last_err = None;
+        for idx in self.hosts.candidates() {
+            let mut config = config.clone();
+            if let Some((host, port)) = self.hosts.addr(idx) {
+                config.host(host);
+                config.port(port);
+            }
+            match self.connect_diagnosed(&config).await {
+                Ok(client) => {
+                    self.hosts.succeeded(idx);
+                    return Ok(client);
+                }
+                Err(e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(addr = %server_addr(&config), error = %e, "host failed, trying the next");
+                    self.hosts.failed(idx);
+                    last_err = Some(e);
+                }
+            }
+        }
+        Err(last_err.expect("hosts is not empty"))
+    }
+
+    async fn connect_diagnosed(&self, config: &tiberius::Config) -> Result<Client, SqlServerError> {
+        match self.connect_to(config).await {
+            Ok(client) => Ok(client),
+            Err(e) if self.diagnose_on_failure => {
+                Err(diagnose::diagnose(&server_addr(config), e).await)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn connect_to(&self, config: &tiberius::Config) -> Result<Client, SqlServerError> {
+        let stream = self.open_stream(config, false).await?;
+        let mut client = Client::connect(config.clone(), stream.compat_write()).await;
+
+        // Servers may redirect again, e.g. a gateway in front of an availability group listener.
+        let mut config = config.clone();
+        let mut redirects = 0;
+        loop {
+            match client {
+                Ok(client) => return Ok(client),
+                Err(Error::Routing { host, port }) if redirects < MAX_REDIRECTS => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(%host, port, "routed to another server");
+                    redirects += 1;
+                    config.host(host);
+                    config.port(port);
+                    let stream = self.open_stream(&config, true).await?;
+                    client = Client::connect(config.clone(), stream.compat_write()).await;
+                }
+                Err(Error::Routing { host, port }) => {
+                    let e = SqlServerError::TooManyRedirects { host, port };
+                    return Err(SqlServerError::connect_failed(ConnectPhase::Login, e));
+                }
+                // Propagate errors
+                Err(err) => return Err(handshake_failed(err)),
+            }
+        }
+    }
+
+    /// Open the transport to the server of `config`. Servers `routed` to are always connected
+    /// over TCP, without SQL Browser lookup.
+    async fn open_stream(
+        &self,
+        config: &tiberius::Config,
+        routed: bool,
+    ) -> Result<BoxedStream, SqlServerError> {
+        #[cfg(feature = "testing")]
+        if let Some(mock) = &self.mock {
+            return Ok(mock.stream());
+        }
+        if let (Some(path), false) = (&self.local_socket, routed) {
+            return connect_local(path)
+                .await
+                .map_err(|e| SqlServerError::connect_failed(ConnectPhase::Tcp, e));
+        }
+        let tcp = if routed {
+            self.connect_addr(&server_addr(config)).await?
+        } else {
+            self.connect_tcp(config).await?
+        };
+        self.configure_socket(&tcp)?;
+        self.wrap_stream(tcp).await
+    }
+
+    async fn wrap_stream(&self, tcp: tokio::net::TcpStream) -> Result<BoxedStream, SqlServerError> {
+        match &self.stream_wrapper {
+            Some(wrapper) => wrapper(tcp)
+                .await
+                .map_err(|e| SqlServerError::connect_failed(ConnectPhase::Tcp, e)),
+            None => Ok(Box::new(tcp)),
+        }
+    }
+
+    /// Apply the same socket options to every connection, redirected ones included.
+    fn configure_socket(&self, tcp: &tokio::net::TcpStream) -> Result<(), SqlServerError> {
+        (self.modify_tcp_stream)(tcp)
+            .and_then(|_| self.set_keepalive(tcp))
+            .and_then(|_| self.set_buffer_sizes(tcp))
+            .map_err(|e| SqlServerError::connect_failed(ConnectPhase::Tcp, e))
+    }
+
+    fn set_keepalive(&self, tcp: &tokio::net::TcpStream) -> tokio::io::Result<()> {
+        let socket = socket2::SockRef::from(tcp);
+        match self.tcp_keepalive {
+            Some((true, time)) => {
+                let mut keepalive = socket2::TcpKeepalive::new();
+                if let Some(time) = time {
+                    keepalive = keepalive.with_time(time);
+                }
+                socket.set_tcp_keepalive(&keepalive)
+            }
+            Some((false, _)) => socket.set_keepalive(false),
+            None => Ok(()),
+        }
+    }
+
+    fn set_buffer_sizes(&self, tcp: &tokio::net::TcpStream) -> tokio::io::Result<()> {
+        let socket = socket2::SockRef::from(tcp);
+        if let Some(size) = self.socket_buffers.0 {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.socket_buffers.1 {
+            socket.set_recv_buffer_size(size)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "sql-browser")]
+    async fn connect_tcp(
+        &self,
+        config: &tiberius::Config,
+    ) -> Result<tokio::net::TcpStream, SqlServerError> {
+        use tiberius::SqlBrowser;
+        if !self.enable_sql_browser {
+            self.connect_addr(&server_addr(config)).await
+        } else {
+            let addr = server_addr(config);
+            self.with_connect_timeout(&addr, tokio::net::TcpStream::connect_named(config))
+                .await
+        }
+    }
+
+    #[cfg(not(feature = "sql-browser"))]
+    async fn connect_tcp(
+        &self,
+        config: &tiberius::Config,
+    ) -> Result<tokio::net::TcpStream, SqlServerError> {
+        self.connect_addr(&server_addr(config)).await
+    }
+
+    async fn connect_addr(&self, addr: &str) -> Result<tokio::net::TcpStream, SqlServerError> {
+        let addrs = match &self.resolver {
+            Some(resolver) => resolver(addr).await,
+            None => tokio::net::lookup_host(addr).await.map(Iterator::collect),
+        };
+        let mut addrs = addrs.map_err(|e| {
+            SqlServerError::connect_failed(ConnectPhase::Dns, SqlServerError::connection(addr, e))
+        })?;
+        addrs.retain(|socket_addr| self.address_family.matches(socket_addr));
+        if addrs.is_empty() {
+            let family = match self.address_family {
+                AddrFamily::Any => "",
+                AddrFamily::V4 => "IPv4 ",
+                AddrFamily::V6 => "IPv6 ",
+            };
+            let e = tokio::io::Error::new(
+                tokio::io::ErrorKind::NotFound,
+                format!("resolver returned no {}address for `{}`", family, addr),
+            );
+            let e = SqlServerError::connection(addr, e);
+            return Err(SqlServerError::connect_failed(ConnectPhase::Dns, e));
+        }
+        if self.multi_subnet_failover && addrs.len() > 1 {
+            let connects = addrs.into_iter().map(|socket_addr| {
+                let connect = tokio::net::TcpStream::connect(socket_addr);
+                Box::pin(async move {
+                    self.with_connect_timeout(&socket_addr.to_string(), connect)
+                        .await
+                })
+            });
+            // The connects still pending are dropped, cancelling them.
+            return match futures_util::future::select_ok(connects).await {
+                Ok((tcp, _)) => Ok(tcp),
+                Err(e) => Err(e),
+            };
+        }
+        let mut last_err = None;
+        for socket_addr in addrs {
+            match self
+                .with_connect_timeout(
+                    &socket_addr.to_string(),
+                    tokio::net::TcpStream::connect(socket_addr),
+                )
+                .await
+            {
+                Ok(tcp) => return Ok(tcp),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.expect("addrs is not empty"))
+    }
+
+    /// Run a TCP `connect` to `addr`, bounded by [`Manager::connect_timeout`].
+    async fn with_connect_timeout<E>(
+        &self,
+        addr: &str,
+        connect: impl std::future::Future<Output = Result<tokio::net::TcpStream, E>>,
+    ) -> Result<tokio::net::TcpStream, SqlServerError>
+    where
+        SqlServerError: From<E>,
+    {
+        let result = match self.connect_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, connect).await {
+                Ok(result) => result.map_err(SqlServerError::from),
+                Err(_) => Err(SqlServerError::Timeout {
+                    kind: TimeoutKind::Connect,
+                }),
+            },
+            None => connect.await.map_err(SqlServerError::from),
+        };
+        result.map_err(|e| {
+            SqlServerError::connect_failed(ConnectPhase::Tcp, SqlServerError::connection(addr, e))
+        })
+    }
+
+    /// The statements setting up the session of new connections, see [`Manager::textsize`],
+    /// [`Manager::lock_timeout`] and [`Manager::init_sql`], in order.
+    fn session_settings(&self) -> Vec<String> {
+        let textsize = self.textsize.map(|size| {
+            let size = if size == -1 { i32::MAX } else { size };
+            format!("SET TEXTSIZE {}", size)
+        });
+        let lock_timeout = self
+            .lock_timeout
+            .map(|ms| format!("SET LOCK_TIMEOUT {}", ms));
+        textsize
+            .into_iter()
+            .chain(lock_timeout)
+            .chain(self.init_sql.iter().cloned())
+            .collect()
+    }
+
+    /// The login's database to switch back to, if the recycle probe found a connection switched
+    /// away from it while no [`database`](Manager::database) is configured.
+    fn switched_database(&self, results: &[Vec<tiberius::Row>]) -> Option<String> {
+        if self.database.is_some() || !self.reset_database_on_recycle || results.len() < 2 {
+            return None;
+        }
+        let row = results[0].first()?;
+        row.try_get::<&str, _>(0).ok().flatten().map(str::to_string)
+    }
+
+    fn recycled(&self, success: bool) {
+        self.stats.recycled(success);
+        if let Some(observer) = &self.observer {
+            observer.on_recycle(success);
+        }
+    }
+
+    /// Pick the runtime for the timeouts, unless chosen with [`Manager::runtime`].
+    fn default_runtime(&mut self) {
+        self.runtime.get_or_insert(TIMEOUT_RUNTIME);
+    }
+}
+
+/// Connect the Unix domain socket at `path`, see [`Manager::unix_socket`].
+#[cfg(unix)]
+async fn connect_local(path: &std::path::Path) -> tokio::io::Result<BoxedStream> {
+    Ok(Box::new(tokio::net::UnixStream::connect(path).await?))
+}
+
+/// Open the named pipe at `path`, see [`Manager::named_pipe`].
+#[cfg(windows)]
+async fn connect_local(path: &std::path::Path) -> tokio::io::Result<BoxedStream> {
+    use tokio::net::windows::named_pipe::ClientOptions;
+    const ERROR_PIPE_BUSY: i32 = 231;
+    loop {
+        match ClientOptions::new().open(path) {
+            Ok(pipe) => return Ok(Box::new(pipe)),
+            // All pipe instances are in use, wait for one to be released.
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) => {}
+            Err(e) => return Err(e),
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+async fn connect_local(_path: &std::path::Path) -> tokio::io::Result<BoxedStream> {
+    Err(tokio::io::ErrorKind::Unsupported.into())
+}
+
+/// `host:port` of `config` to connect to.
+///
+/// tiberius joins host and port naively, yielding e.g. `::1:1433` for an IPv6 literal, which no
+/// resolver accepts, so these are bracketed as in `[::1]:1433`.
+fn server_addr(config: &tiberius::Config) -> String {
+    let addr = config.get_addr();
+    match addr.rsplit_once(':') {
+        Some((host, port)) if is_ipv6_literal(host) => format!("[{}]:{}", host, port),
+        _ => addr,
+    }
+}
+
+/// Whether `host` is an unbracketed IPv6 address, with an optional `%` zone.
+fn is_ipv6_literal(host: &str) -> bool {
+    let address = host.split_once('%').map_or(host, |(address, _)| address);
+    address.parse::<std::net::Ipv6Addr>().is_ok()
+}
+
+/// Error for a connection string tiberius failed to parse, without the offending token quoted
+/// by the parser as it may be part of a password.
+fn malformed_conn_str(e: Error) -> SqlServerError {
+    match e {
+        Error::Conversion(message) => {
+            let end = message
+                .find('`')
+                .or_else(|| message.find("found:").map(|idx| idx + "found".len()))
+                .unwrap_or(message.len());
+            SqlServerError::InvalidConfig(format!(
+                "malformed connection string: {}",
+                message[..end].trim_end()
+            ))
+        }
+        e => e.into(),
+    }
+}
+
+/// Value of the environment variable `name`, `None` if unset or empty.
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> SqlServerResult<Option<T>> {
+    env_var(name)
+        .map(|value| {
+            value.parse().map_err(|_| {
+                SqlServerError::InvalidConfig(format!(
+                    "`{}` is not a valid number: {}",
+                    name, value
+                ))
+            })
+        })
+        .transpose()
+}
+
+/// Tag an error of [`Client::connect`] with the phase it stems from.
+fn handshake_failed(e: Error) -> SqlServerError {
+    let phase = match e {
+        Error::Tls(_) => ConnectPhase::Tls,
+        _ => ConnectPhase::Login,
+    };
+    SqlServerError::connect_failed(phase, e)
+}
+
+/// Prepend `comment` to `sql` as a `/* */` block comment. Comment delimiters within `comment`
+/// are defused, so it can neither end the comment early nor nest one.
+fn comment_sql(comment: &str, sql: &str) -> String {
+    let comment = comment.replace("*/", "* /").replace("/*", "/ *");
+    format!("/* {} */ {}", comment, sql)
+}
+
+/// Quote `name` as a bracketed sql server identifier, escaping `]`.
+fn quote_identifier(name: &str) -> String {
+    format!("[{}]", name.replace(']', "]]"))
 }
 
+#[derive(Clone)]
 struct Hooks {
-    pre_recycle: Vec<Hook<Manager>>,
-    post_recycle: Vec<Hook<Manager>>,
-    post_create: Vec<Hook<Manager>>,
+    pre_recycle: Vec<HookFactory>,
+    post_recycle: Vec<HookFactory>,
+    post_create: Vec<HookFactory>,
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for Hooks {
     fn default() -> Self {
         Hooks {
-            pre_recycle: Vec::<Hook<Manager>>::new(),
-            post_recycle: Vec::<Hook<Manager>>::new(),
-            post_create: Vec::<Hook<Manager>>::new(),
+            pre_recycle: Vec::<HookFactory>::new(),
+            post_recycle: Vec::<HookFactory>::new(),
+            post_create: Vec::<HookFactory>::new(),
         }
     }
 }
@@ -0,0 +1,165 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use deadpool::managed::{self, Object, Timeouts};
+
+use crate::{Manager, MetricsCallback, Pool, SqlServerError, SqlServerResult, MIN_IDLE_INTERVAL};
+
+type ManagedPool = managed::Pool<Manager>;
+
+fn runtime_handle(option: &str) -> SqlServerResult<tokio::runtime::Handle> {
+    tokio::runtime::Handle::try_current().map_err(|_| {
+        SqlServerError::InvalidConfig(format!(
+            "`{}` requires create_pool to be called within a tokio runtime",
+            option
+        ))
+    })
+}
+
+/// The periodic work of a pool's background task, see [`spawn`].
+pub(crate) struct Duties {
+    /// Open connections until this many are idle, see [`Manager::min_idle`].
+    pub(crate) min_idle: usize,
+    /// Ping idle connections at this interval, see [`Manager::idle_ping_interval`].
+    pub(crate) idle_ping: Option<Duration>,
+    /// Report the pool status, see [`Manager::with_metrics_callback`].
+    pub(crate) status: Option<(Duration, Arc<MetricsCallback>)>,
+}
+
+/// Run the `duties` of `pool` on a background task, if any, until the pool is closed or
+/// dropped.
+pub(crate) fn spawn(pool: &Pool, duties: Duties) -> SqlServerResult<()> {
+    let option = match &duties {
+        Duties { min_idle: 1.., .. } => "min_idle",
+        Duties {
+            idle_ping: Some(_), ..
+        } => "idle_ping_interval",
+        Duties {
+            status: Some(_), ..
+        } => "with_metrics_callback",
+        _ => return Ok(()),
+    };
+    let handle = runtime_handle(option)?;
+    let handles = Arc::downgrade(&pool.handles);
+    let pool = pool.inner.clone();
+    handle.spawn(async move {
+        let now = Instant::now();
+        let mut next_top_up = (duties.min_idle > 0).then_some(now);
+        // Connections are fresh at first, the first ping is due after one interval.
+        let mut next_ping = duties.idle_ping.map(|interval| now + interval);
+        let mut next_status = duties.status.as_ref().map(|_| now);
+        while let Some(due) = [next_top_up, next_ping, next_status]
+            .into_iter()
+            .flatten()
+            .min()
+        {
+            tokio::time::sleep_until(due.into()).await;
+            if pool.is_closed() || handles.strong_count() == 0 {
+                break;
+            }
+            if next_status.is_some_and(|at| at <= due) {
+                if let Some((interval, callback)) = &duties.status {
+                    callback(pool.status());
+                    next_status = Some(due + *interval);
+                }
+            }
+            if next_ping.is_some_and(|at| at <= due) {
+                ping_idle(&pool).await;
+                next_ping = duties.idle_ping.map(|interval| due + interval);
+            }
+            if next_top_up.is_some_and(|at| at <= due) {
+                top_up_idle(&pool, duties.min_idle).await;
+                next_top_up = Some(due + MIN_IDLE_INTERVAL);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Check out the idle connections of `pool` one at a time, returning each right away, so the
+/// recycle probe pings them and the pool discards the ones which fail.
+///
+/// The pool hands out its idle connections first in, first out, so returned connections queue
+/// up behind the others and each is pinged once, stopping early if a pinged one comes around
+/// again.
+async fn ping_idle(pool: &ManagedPool) {
+    let status = pool.status();
+    let mut pinged = HashSet::new();
+    // Failing connections are discarded while checking out, they count as pinged too.
+    while pinged.len() + status.size.saturating_sub(pool.status().size) < status.available {
+        match pool.timeout_get(&no_wait(pool)).await {
+            Ok(conn) if pinged.insert(Object::metrics(&conn).created) => {}
+            _ => break,
+        }
+    }
+}
+
+/// Open the connections missing to `min_idle` idle ones and hand them to the pool.
+///
+/// The pool only opens connections while none is idle, so the connections are opened first,
+/// without the pool, keeping the idle ones available meanwhile. Then the idle ones are checked
+/// out just long enough for the pool to take over the opened ones through [`Manager::opened`].
+async fn top_up_idle(pool: &ManagedPool, min_idle: usize) {
+    let status = pool.status();
+    // Leave the slots to waiting requests, they open connections themselves.
+    if status.waiting > 0 {
+        return;
+    }
+    let missing = min_idle
+        .saturating_sub(status.available)
+        .min(status.max_size.saturating_sub(status.size));
+    let mut opened = Vec::with_capacity(missing);
+    for _ in 0..missing {
+        match managed::Manager::create(pool.manager()).await {
+            Ok(client) => opened.push(client),
+            Err(_) => break,
+        }
+    }
+    if opened.is_empty() {
+        return;
+    }
+    pool.manager().opened.lock().unwrap().extend(opened);
+    let mut checked_out = Vec::new();
+    while !pool.manager().opened.lock().unwrap().is_empty() {
+        match pool.timeout_get(&no_wait(pool)).await {
+            Ok(conn) => checked_out.push(conn),
+            Err(_) => break,
+        }
+    }
+    // Not taken over without waiting for a slot, closed.
+    pool.manager().opened.lock().unwrap().clear();
+}
+
+/// Open up to `count` connections, capped at the pool's max size, and return them to `pool`.
+pub(crate) async fn prefill(pool: &ManagedPool, count: usize) -> SqlServerResult<()> {
+    let count = count.min(pool.status().max_size);
+    let mut opened = Vec::with_capacity(count);
+    for _ in 0..count {
+        opened.push(pool.get().await?);
+    }
+    Ok(())
+}
+
+/// Check out every idle connection of `pool`, without waiting for any.
+pub(crate) async fn take_idle(pool: &ManagedPool) -> Vec<Object<Manager>> {
+    // Never wait for a slot, so real traffic is not competed with.
+    let timeouts = no_wait(pool);
+    // Hold on to every acquired connection, so the same one isn't handed out twice.
+    let mut idle: Vec<Object<Manager>> = Vec::new();
+    for _ in 0..pool.status().available {
+        match pool.timeout_get(&timeouts).await {
+            Ok(conn) => idle.push(conn),
+            Err(_) => break,
+        }
+    }
+    idle
+}
+
+/// The timeouts of `pool`, without waiting for a slot.
+fn no_wait(pool: &ManagedPool) -> Timeouts {
+    Timeouts {
+        wait: Some(Duration::ZERO),
+        ..pool.timeouts()
+    }
+}
@@ -0,0 +1,547 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tiberius::EncryptionLevel;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+use crate::{BoxedStream, Manager};
+
+/// Packet size of the mock server, tiberius' default.
+const PACKET_SIZE: usize = 4096;
+const HEADER_SIZE: usize = 8;
+
+const PACKET_BATCH: u8 = 0x01;
+const PACKET_RPC: u8 = 0x03;
+const PACKET_RESULT: u8 = 0x04;
+const PACKET_ATTENTION: u8 = 0x06;
+const PACKET_BULK_LOAD: u8 = 0x07;
+const PACKET_LOGIN: u8 = 0x10;
+const PACKET_PRELOGIN: u8 = 0x12;
+
+const TOKEN_COLMETADATA: u8 = 0x81;
+const TOKEN_ERROR: u8 = 0xAA;
+const TOKEN_LOGINACK: u8 = 0xAD;
+const TOKEN_ROW: u8 = 0xD1;
+const TOKEN_ENVCHANGE: u8 = 0xE3;
+const TOKEN_DONE: u8 = 0xFD;
+
+const DONE_FINAL: u16 = 0x00;
+const DONE_MORE: u16 = 0x01;
+const DONE_ERROR: u16 = 0x02;
+const DONE_COUNT: u16 = 0x10;
+const DONE_ATTENTION: u16 = 0x20;
+
+const ENVCHANGE_ROUTING: u8 = 20;
+
+const TYPE_BITN: u8 = 0x68;
+const TYPE_INTN: u8 = 0x26;
+const TYPE_FLOATN: u8 = 0x6D;
+const TYPE_NVARCHAR: u8 = 0xE7;
+
+/// The query pooled connections run on recycle, answered without consuming a response.
+const RECYCLE_PROBE: &str = "SELECT @@TRANCOUNT";
+
+/// A canned response of [`Manager::mock`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockResult {
+    /// A result set, see [`MockResult::rows`].
+    Rows {
+        /// Column names.
+        columns: Vec<String>,
+        /// The rows, each holding one value per column.
+        rows: Vec<Vec<MockValue>>,
+    },
+    /// Rows affected by a statement without result set, e.g. an `UPDATE`.
+    Affected(u64),
+    /// A server error, surfacing as [`tiberius::error::Error::Server`].
+    Error {
+        /// The error number, see [`tiberius::error::Error::code`].
+        code: u32,
+        /// The error message.
+        message: String,
+    },
+    /// A server error rejecting the next login, see [`MockResult::login_error`].
+    LoginError {
+        /// The error number, e.g. 18456.
+        code: u32,
+        /// The error state.
+        state: u8,
+        /// The error message.
+        message: String,
+    },
+    /// A redirect of the next login to another server, see [`MockResult::routing`].
+    Routing {
+        /// The server redirected to.
+        host: String,
+        /// Its port.
+        port: u16,
+    },
+    /// The results of the statements of a batch, in order, e.g. several `UPDATE`s.
+    Batch(Vec<MockResult>),
+    /// Another result, sent after a delay, see [`MockResult::delayed`].
+    Delayed {
+        /// How long the server takes to respond.
+        delay: Duration,
+        /// The result sent.
+        result: Box<MockResult>,
+    },
+}
+
+impl MockResult {
+    /// A result set with the `columns` and `rows`.
+    ///
+    /// The sql type of a column is derived from its values, so all non-null values of a column
+    /// must be of the same variant.
+    ///
+    /// # Panics
+    /// If a row doesn't hold one value per column, or a column mixes variants.
+    pub fn rows(columns: &[&str], rows: Vec<Vec<MockValue>>) -> Self {
+        for row in &rows {
+            assert_eq!(row.len(), columns.len(), "row doesn't match the columns");
+        }
+        for idx in 0..columns.len() {
+            let mut types = rows.iter().filter_map(|row| row[idx].sql_type());
+            if let Some(first) = types.next() {
+                assert!(
+                    types.all(|ty| ty == first),
+                    "column `{}` mixes value variants",
+                    columns[idx]
+                );
+            }
+        }
+        Self::Rows {
+            columns: columns.iter().map(|name| name.to_string()).collect(),
+            rows,
+        }
+    }
+
+    /// A server error with `code` and `message`.
+    pub fn error(code: u32, message: impl ToString) -> Self {
+        Self::Error {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    /// A server error with `code` and `state` failing the next connection's login, instead of
+    /// answering a query.
+    pub fn login_error(code: u32, state: u8, message: impl ToString) -> Self {
+        Self::LoginError {
+            code,
+            state,
+            message: message.to_string(),
+        }
+    }
+
+    /// Redirect the next connection's login to `host` and `port`, as an Azure SQL gateway or an
+    /// availability group listener does. The redirected connection is served by the mock too.
+    pub fn routing(host: impl ToString, port: u16) -> Self {
+        Self::Routing {
+            host: host.to_string(),
+            port,
+        }
+    }
+
+    /// `result`, sent after `delay`, e.g. to test timeouts.
+    pub fn delayed(delay: Duration, result: MockResult) -> Self {
+        Self::Delayed {
+            delay,
+            result: Box::new(result),
+        }
+    }
+}
+
+/// A value of a [`MockResult::Rows`] row.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockValue {
+    /// `NULL`.
+    Null,
+    /// A `bit`.
+    Bool(bool),
+    /// A `smallint`.
+    SmallInt(i16),
+    /// An `int`.
+    Int(i32),
+    /// A `bigint`.
+    BigInt(i64),
+    /// A `float`.
+    Float(f64),
+    /// An `nvarchar`.
+    String(String),
+}
+
+impl MockValue {
+    /// Type and size in the column metadata, `None` for nulls.
+    fn sql_type(&self) -> Option<(u8, u8)> {
+        match self {
+            MockValue::Null => None,
+            MockValue::Bool(_) => Some((TYPE_BITN, 1)),
+            MockValue::SmallInt(_) => Some((TYPE_INTN, 2)),
+            MockValue::Int(_) => Some((TYPE_INTN, 4)),
+            MockValue::BigInt(_) => Some((TYPE_INTN, 8)),
+            MockValue::Float(_) => Some((TYPE_FLOATN, 8)),
+            MockValue::String(_) => Some((TYPE_NVARCHAR, 0)),
+        }
+    }
+
+    fn encode(&self, ty: (u8, u8), dst: &mut Vec<u8>) {
+        match (self, ty.0) {
+            (MockValue::Null, TYPE_NVARCHAR) => dst.extend_from_slice(&0xFFFFu16.to_le_bytes()),
+            (MockValue::Null, _) => dst.push(0),
+            (MockValue::Bool(v), _) => dst.extend_from_slice(&[1, *v as u8]),
+            (MockValue::SmallInt(v), _) => {
+                dst.push(2);
+                dst.extend_from_slice(&v.to_le_bytes());
+            }
+            (MockValue::Int(v), _) => {
+                dst.push(4);
+                dst.extend_from_slice(&v.to_le_bytes());
+            }
+            (MockValue::BigInt(v), _) => {
+                dst.push(8);
+                dst.extend_from_slice(&v.to_le_bytes());
+            }
+            (MockValue::Float(v), _) => {
+                dst.push(8);
+                dst.extend_from_slice(&v.to_le_bytes());
+            }
+            (MockValue::String(v), _) => {
+                let bytes = utf16(v);
+                dst.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+                dst.extend_from_slice(&bytes);
+            }
+        }
+    }
+}
+
+/// Serves [`MockResult`]s to the connections of a mocked [`Manager`].
+pub(crate) struct MockServer {
+    responses: Mutex<VecDeque<MockResult>>,
+    recycle_responses: Mutex<VecDeque<MockResult>>,
+    /// The sql text of the batches received, see [`Manager::mock_batches`].
+    batches: Mutex<Vec<String>>,
+}
+
+impl MockServer {
+    /// The transport of a new in-memory server session.
+    pub(crate) fn stream(self: &Arc<Self>) -> BoxedStream {
+        let (client, server) = tokio::io::duplex(PACKET_SIZE * 4);
+        tokio::spawn(self.clone().serve(server));
+        Box::new(client)
+    }
+
+    async fn serve(self: Arc<Self>, mut stream: DuplexStream) -> std::io::Result<()> {
+        loop {
+            let (ty, payload) = read_message(&mut stream).await?;
+            let response = match ty {
+                PACKET_PRELOGIN => prelogin_response(),
+                PACKET_LOGIN => {
+                    let mut responses = self.responses.lock().unwrap();
+                    match responses.front() {
+                        Some(MockResult::LoginError { .. } | MockResult::Routing { .. }) => {
+                            responses
+                                .pop_front()
+                                .map(|e| e.encode())
+                                .unwrap_or_default()
+                        }
+                        _ => {
+                            let mut tokens = login_ack();
+                            done(&mut tokens, DONE_FINAL, 0);
+                            tokens
+                        }
+                    }
+                }
+                PACKET_BATCH | PACKET_RPC | PACKET_BULK_LOAD => {
+                    let result = if ty == PACKET_BATCH {
+                        let text = batch_text(&payload);
+                        let recycle = text.trim_end().ends_with(RECYCLE_PROBE);
+                        self.batches.lock().unwrap().push(text);
+                        match recycle {
+                            true => Some(
+                                self.recycle_responses
+                                    .lock()
+                                    .unwrap()
+                                    .pop_front()
+                                    .unwrap_or_else(|| {
+                                        MockResult::rows(&[""], vec![vec![MockValue::Int(0)]])
+                                    }),
+                            ),
+                            false => self.responses.lock().unwrap().pop_front(),
+                        }
+                    } else {
+                        self.responses.lock().unwrap().pop_front()
+                    };
+                    match result {
+                        Some(MockResult::Delayed { delay, result }) => {
+                            tokio::time::sleep(delay).await;
+                            result.encode()
+                        }
+                        Some(result) => result.encode(),
+                        None => MockResult::Affected(0).encode(),
+                    }
+                }
+                PACKET_ATTENTION => {
+                    let mut tokens = Vec::new();
+                    done(&mut tokens, DONE_ATTENTION, 0);
+                    tokens
+                }
+                _ => continue,
+            };
+            write_message(&mut stream, &response).await?;
+        }
+    }
+}
+
+impl MockResult {
+    fn encode(&self) -> Vec<u8> {
+        let mut dst = Vec::new();
+        match self {
+            MockResult::Rows { columns, rows } => {
+                let types: Vec<_> = (0..columns.len())
+                    .map(|idx| {
+                        rows.iter()
+                            .find_map(|row| row[idx].sql_type())
+                            .unwrap_or((TYPE_NVARCHAR, 0))
+                    })
+                    .collect();
+                dst.push(TOKEN_COLMETADATA);
+                dst.extend_from_slice(&(columns.len() as u16).to_le_bytes());
+                for (name, (ty, size)) in columns.iter().zip(&types) {
+                    // User type and the nullable and updateable flags, bulk loads skip
+                    // columns not updateable.
+                    dst.extend_from_slice(&0u32.to_le_bytes());
+                    dst.extend_from_slice(&0x0009u16.to_le_bytes());
+                    dst.push(*ty);
+                    if *ty == TYPE_NVARCHAR {
+                        dst.extend_from_slice(&8000u16.to_le_bytes());
+                        // Collation SQL_Latin1_General_CP1_CI_AS.
+                        dst.extend_from_slice(&[0x09, 0x04, 0xD0, 0x00, 0x34]);
+                    } else {
+                        dst.push(*size);
+                    }
+                    b_varchar(&mut dst, name);
+                }
+                for row in rows {
+                    dst.push(TOKEN_ROW);
+                    for (value, ty) in row.iter().zip(&types) {
+                        value.encode(*ty, &mut dst);
+                    }
+                }
+                done(&mut dst, DONE_COUNT, rows.len() as u64);
+            }
+            MockResult::Affected(count) => done(&mut dst, DONE_COUNT, *count),
+            MockResult::Error { code, message } => error(&mut dst, *code, 1, message),
+            MockResult::LoginError {
+                code,
+                state,
+                message,
+            } => error(&mut dst, *code, *state, message),
+            MockResult::Routing { host, port } => {
+                let host = utf16(host);
+                let mut routing = vec![0];
+                routing.extend_from_slice(&port.to_le_bytes());
+                routing.extend_from_slice(&((host.len() / 2) as u16).to_le_bytes());
+                routing.extend_from_slice(&host);
+                let mut token = vec![ENVCHANGE_ROUTING];
+                token.extend_from_slice(&(routing.len() as u16).to_le_bytes());
+                token.extend_from_slice(&routing);
+                // No old value.
+                token.extend_from_slice(&0u16.to_le_bytes());
+                dst.extend(login_ack());
+                dst.push(TOKEN_ENVCHANGE);
+                dst.extend_from_slice(&(token.len() as u16).to_le_bytes());
+                dst.extend_from_slice(&token);
+                done(&mut dst, DONE_FINAL, 0);
+            }
+            MockResult::Batch(results) => {
+                for (idx, result) in results.iter().enumerate() {
+                    let mut tokens = result.encode();
+                    if idx + 1 < results.len() {
+                        // Flag the trailing done token of all but the last statement.
+                        let status = tokens.len() - 12;
+                        tokens[status] |= DONE_MORE as u8;
+                    }
+                    dst.extend_from_slice(&tokens);
+                }
+            }
+            MockResult::Delayed { result, .. } => return result.encode(),
+        }
+        dst
+    }
+}
+
+impl Manager {
+    /// A manager whose connections talk to an in-memory server instead of SQL Server, answering
+    /// each query with the next of `responses`, for testing code depending on the pool.
+    ///
+    /// Every request sent on any connection of the pool takes the next response, including
+    /// queries run by hooks such as [`init_sql`](Manager::init_sql). The recycle check doesn't
+    /// take one, see [`mock_recycle`](Manager::mock_recycle). A bulk load takes three: the
+    /// result set whose columns are loaded, the response to `INSERT BULK` and the affected rows.
+    /// Once all responses are used up, queries return no rows.
+    /// ```
+    /// # use deadpool_tiberius::{Manager, MockResult, MockValue};
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> deadpool_tiberius::SqlServerResult<()> {
+    /// let pool = Manager::mock(vec![MockResult::rows(
+    ///     &["id", "name"],
+    ///     vec![vec![MockValue::Int(1), MockValue::String("alice".into())]],
+    /// )])
+    /// .create_pool()?;
+    ///
+    /// let mut conn = pool.get().await?;
+    /// let row = conn.simple_query("SELECT id, name FROM users").await?.into_row().await?;
+    /// assert_eq!(row.unwrap().get::<&str, _>("name"), Some("alice"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+    pub fn mock(responses: Vec<MockResult>) -> Self {
+        let mut manager = Self::new()
+            .basic_authentication("mock", "mock")
+            .encryption(EncryptionLevel::NotSupported);
+        manager.mock = Some(Arc::new(MockServer {
+            responses: Mutex::new(responses.into()),
+            recycle_responses: Mutex::new(VecDeque::new()),
+            batches: Mutex::new(Vec::new()),
+        }));
+        manager
+    }
+
+    /// Answer the recycle batches of a [`mock`](Manager::mock) with `responses` in order, e.g.
+    /// an error to fail a recycle, or [`MockResult::delayed`] to time it out. Once used up,
+    /// recycle batches report no open transaction. Does nothing if not mocked.
+    ///
+    /// The response must fit the whole batch, which ends with `SELECT @@TRANCOUNT`: a leaked
+    /// transaction is mocked by a [`MockResult::Batch`] whose last result set holds a count
+    /// above 0.
+    #[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+    pub fn mock_recycle(self, responses: Vec<MockResult>) -> Self {
+        if let Some(mock) = &self.mock {
+            *mock.recycle_responses.lock().unwrap() = responses.into();
+        }
+        self
+    }
+
+    /// The sql text of the batches the [`mock`](Manager::mock) server received so far, oldest
+    /// first, including recycle batches and those of hooks. RPC requests, i.e. queries with
+    /// parameters, aren't listed. Empty if not mocked.
+    /// ```
+    /// # use deadpool_tiberius::{Manager, MockResult};
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> deadpool_tiberius::SqlServerResult<()> {
+    /// let pool = Manager::mock(vec![]).create_pool()?;
+    /// pool.get().await?.simple_query("DELETE FROM sessions").await?;
+    /// assert_eq!(pool.manager().mock_batches(), ["DELETE FROM sessions"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+    pub fn mock_batches(&self) -> Vec<String> {
+        self.mock
+            .as_ref()
+            .map(|mock| mock.batches.lock().unwrap().clone())
+            .unwrap_or_default()
+    }
+}
+
+fn utf16(value: &str) -> Vec<u8> {
+    value.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn b_varchar(dst: &mut Vec<u8>, value: &str) {
+    dst.push(value.encode_utf16().count() as u8);
+    dst.extend_from_slice(&utf16(value));
+}
+
+fn error(dst: &mut Vec<u8>, code: u32, state: u8, message: &str) {
+    let mut token = Vec::new();
+    token.extend_from_slice(&code.to_le_bytes());
+    // State and severity.
+    token.extend_from_slice(&[state, 16]);
+    let message = utf16(message);
+    token.extend_from_slice(&((message.len() / 2) as u16).to_le_bytes());
+    token.extend_from_slice(&message);
+    b_varchar(&mut token, "mock");
+    b_varchar(&mut token, "");
+    token.extend_from_slice(&1u32.to_le_bytes());
+    dst.push(TOKEN_ERROR);
+    dst.extend_from_slice(&(token.len() as u16).to_le_bytes());
+    dst.extend_from_slice(&token);
+    done(dst, DONE_ERROR, 0);
+}
+
+fn done(dst: &mut Vec<u8>, status: u16, rows: u64) {
+    dst.push(TOKEN_DONE);
+    dst.extend_from_slice(&status.to_le_bytes());
+    dst.extend_from_slice(&0u16.to_le_bytes());
+    dst.extend_from_slice(&rows.to_le_bytes());
+}
+
+fn prelogin_response() -> Vec<u8> {
+    // Version and encryption option, followed by the terminator and their data.
+    let mut dst = vec![0x00, 0, 11, 0, 6, 0x01, 0, 17, 0, 1, 0xFF];
+    dst.extend_from_slice(&[16, 0, 0, 0, 0, 0]);
+    dst.push(EncryptionLevel::NotSupported as u8);
+    dst
+}
+
+fn login_ack() -> Vec<u8> {
+    let mut ack = vec![1];
+    // TDS 7.4
+    ack.extend_from_slice(&0x7400_0004u32.to_be_bytes());
+    b_varchar(&mut ack, "deadpool-tiberius mock");
+    ack.extend_from_slice(&0x1000_0000u32.to_le_bytes());
+    let mut dst = vec![TOKEN_LOGINACK];
+    dst.extend_from_slice(&(ack.len() as u16).to_le_bytes());
+    dst.extend_from_slice(&ack);
+    dst
+}
+
+/// The sql text of a batch request, following the ALL_HEADERS block.
+fn batch_text(payload: &[u8]) -> String {
+    let headers = payload
+        .get(..4)
+        .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize)
+        .unwrap_or_default();
+    let text: Vec<u16> = payload
+        .get(headers..)
+        .unwrap_or_default()
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&text)
+}
+
+/// Read packets up to the end of a message, returning its type and payload.
+async fn read_message(stream: &mut DuplexStream) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut payload = Vec::new();
+    loop {
+        let mut header = [0u8; HEADER_SIZE];
+        stream.read_exact(&mut header).await?;
+        let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let start = payload.len();
+        payload.resize(start + len.saturating_sub(HEADER_SIZE), 0);
+        stream.read_exact(&mut payload[start..]).await?;
+        if header[1] & 0x01 != 0 {
+            return Ok((header[0], payload));
+        }
+    }
+}
+
+async fn write_message(stream: &mut DuplexStream, payload: &[u8]) -> std::io::Result<()> {
+    let mut chunks = payload.chunks(PACKET_SIZE - HEADER_SIZE).peekable();
+    let mut id = 1u8;
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        let len = (chunk.len() + HEADER_SIZE) as u16;
+        let mut packet = vec![PACKET_RESULT, last as u8];
+        packet.extend_from_slice(&len.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, id, 0]);
+        packet.extend_from_slice(chunk);
+        stream.write_all(&packet).await?;
+        id = id.wrapping_add(1);
+    }
+    stream.flush().await
+}
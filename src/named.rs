@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+
+use tiberius::ToSql;
+
+use crate::{SqlServerError, SqlServerResult};
+
+/// Rewrite `@name` placeholders in `sql` into tiberius' positional `@P1`, `@P2`..., returning
+/// the rewritten sql along with the parameters in positional order.
+///
+/// Repeated names share the same position. Placeholders within string literals, quoted
+/// identifiers and comments, including nested block comments, are left untouched, as are `@@`
+/// system functions and local variables declared in `sql` with `DECLARE`.
+pub(crate) fn bind_named<'a>(
+    sql: &str,
+    params: &HashMap<&str, &'a dyn ToSql>,
+) -> SqlServerResult<(String, Vec<&'a dyn ToSql>)> {
+    let mut out = String::with_capacity(sql.len());
+    let mut names: Vec<&str> = Vec::new();
+    let mut locals: Vec<&str> = Vec::new();
+    let mut bound = Vec::new();
+    let mut rest = sql;
+    // Tracks `DECLARE @a int, @b int` lists, up to the next `;` or statement.
+    let mut prev = Prev::Other;
+    let mut declaring = false;
+    let mut depth = 0usize;
+
+    while let Some(c) = rest.chars().next() {
+        let skip = match c {
+            '\'' | '"' | '[' => {
+                prev = Prev::Other;
+                closing(rest, if c == '[' { "]" } else { &rest[..1] }, 1)
+            }
+            '-' if rest.starts_with("--") => rest.find('\n').unwrap_or(rest.len()),
+            '/' if rest.starts_with("/*") => block_comment(rest),
+            c if c.is_whitespace() => c.len_utf8(),
+            '@' if rest.starts_with("@@") => 2 + ident_len(&rest[2..]),
+            '@' if ident_len(&rest[1..]) > 0 => {
+                let name = &rest[1..1 + ident_len(&rest[1..])];
+                let declared = prev == Prev::Declare
+                    || (declaring
+                        && depth == 0
+                        && prev == Prev::Comma
+                        && !params.contains_key(name));
+                if declared && !locals.contains(&name) {
+                    locals.push(name);
+                }
+                prev = Prev::Other;
+                rest = &rest[1 + name.len()..];
+                if locals.contains(&name) {
+                    out.push('@');
+                    out.push_str(name);
+                    continue;
+                }
+                let position = match names.iter().position(|n| *n == name) {
+                    Some(position) => position,
+                    None => {
+                        let value = params
+                            .get(name)
+                            .ok_or_else(|| SqlServerError::MissingParameter(name.to_string()))?;
+                        names.push(name);
+                        bound.push(*value);
+                        names.len() - 1
+                    }
+                };
+                out.push_str(&format!("@P{}", position + 1));
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' || c == '#' => {
+                let word = &rest[..ident_len(rest)];
+                if word.eq_ignore_ascii_case("DECLARE") {
+                    prev = Prev::Declare;
+                    declaring = true;
+                } else {
+                    if depth == 0 && STATEMENTS.iter().any(|s| word.eq_ignore_ascii_case(s)) {
+                        declaring = false;
+                    }
+                    prev = Prev::Other;
+                }
+                word.len()
+            }
+            c => {
+                match c {
+                    ',' => {
+                        prev = Prev::Comma;
+                        out.push(c);
+                        rest = &rest[1..];
+                        continue;
+                    }
+                    ';' => declaring = false,
+                    '(' => depth += 1,
+                    ')' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                prev = Prev::Other;
+                c.len_utf8()
+            }
+        };
+        out.push_str(&rest[..skip]);
+        rest = &rest[skip..];
+    }
+
+    Ok((out, bound))
+}
+
+/// Keywords starting a statement, which ends a `DECLARE` list not terminated by `;`. `END` is
+/// left out as it also closes `CASE` expressions of declared defaults.
+const STATEMENTS: &[&str] = &[
+    "ALTER",
+    "BEGIN",
+    "BREAK",
+    "CLOSE",
+    "COMMIT",
+    "CONTINUE",
+    "CREATE",
+    "DEALLOCATE",
+    "DELETE",
+    "DROP",
+    "EXEC",
+    "EXECUTE",
+    "FETCH",
+    "GOTO",
+    "IF",
+    "INSERT",
+    "MERGE",
+    "OPEN",
+    "PRINT",
+    "RAISERROR",
+    "RETURN",
+    "ROLLBACK",
+    "SAVE",
+    "SELECT",
+    "SET",
+    "THROW",
+    "TRUNCATE",
+    "UPDATE",
+    "USE",
+    "WAITFOR",
+    "WHILE",
+    "WITH",
+];
+
+/// The token before a `@name`, ignoring whitespace and comments.
+#[derive(PartialEq)]
+enum Prev {
+    Declare,
+    Comma,
+    Other,
+}
+
+/// Length of the block comment at the start of `s`, comments nest as in T-SQL.
+fn block_comment(s: &str) -> usize {
+    let mut depth = 0;
+    let mut pos = 0;
+    while pos < s.len() {
+        if s[pos..].starts_with("/*") {
+            depth += 1;
+            pos += 2;
+        } else if s[pos..].starts_with("*/") {
+            depth -= 1;
+            pos += 2;
+            if depth == 0 {
+                return pos;
+            }
+        } else {
+            pos += s[pos..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    s.len()
+}
+
+/// Length of the quoted section at the start of `s` up to and including `end`, a doubled
+/// `end` is treated as escaped.
+fn closing(s: &str, end: &str, open_len: usize) -> usize {
+    let mut pos = open_len;
+    while let Some(found) = s[pos..].find(end) {
+        pos += found + end.len();
+        if end.len() == 1 && s[pos..].starts_with(end) {
+            pos += end.len();
+            continue;
+        }
+        return pos;
+    }
+    s.len()
+}
+
+fn ident_len(s: &str) -> usize {
+    s.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '#' || c == '$'))
+        .unwrap_or(s.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tiberius::ToSql;
+
+    use super::*;
+
+    fn bind(sql: &str, names: &[&'static str]) -> SqlServerResult<(String, usize)> {
+        let params: HashMap<&str, &dyn ToSql> = names.iter().map(|n| (*n, &1 as _)).collect();
+        bind_named(sql, &params).map(|(sql, bound)| (sql, bound.len()))
+    }
+
+    #[test]
+    fn positions() {
+        let (sql, bound) = bind("SELECT @a, @b, @a", &["a", "b", "unused"]).unwrap();
+        assert_eq!(sql, "SELECT @P1, @P2, @P1");
+        assert_eq!(bound, 2);
+        let (sql, _) = bind("WHERE x=@a AND y=@bc_1", &["a", "bc_1"]).unwrap();
+        assert_eq!(sql, "WHERE x=@P1 AND y=@P2");
+    }
+
+    #[test]
+    fn missing() {
+        let err = bind("SELECT @a, @b", &["a"]).unwrap_err();
+        assert!(matches!(err, SqlServerError::MissingParameter(name) if name == "b"));
+    }
+
+    #[test]
+    fn system_functions() {
+        let (sql, bound) = bind("SELECT @@TRANCOUNT, @@ROWCOUNT, @a", &["a"]).unwrap();
+        assert_eq!(sql, "SELECT @@TRANCOUNT, @@ROWCOUNT, @P1");
+        assert_eq!(bound, 1);
+    }
+
+    #[test]
+    fn literals() {
+        let sql = "SELECT '@a', N'it''s @a', \"@a\", [@a], @a";
+        assert_eq!(
+            bind(sql, &["a"]).unwrap().0,
+            "SELECT '@a', N'it''s @a', \"@a\", [@a], @P1"
+        );
+        assert_eq!(bind("SELECT 'x'' @b'", &[]).unwrap().0, "SELECT 'x'' @b'");
+    }
+
+    #[test]
+    fn comments() {
+        let sql = "SELECT @a -- @b\n/* @c */ FROM t /* a /* nested @d */ @e */ WHERE @a = 1";
+        let (out, bound) = bind(sql, &["a"]).unwrap();
+        assert_eq!(
+            out,
+            "SELECT @P1 -- @b\n/* @c */ FROM t /* a /* nested @d */ @e */ WHERE @P1 = 1"
+        );
+        assert_eq!(bound, 1);
+        assert_eq!(bind("SELECT 1 -- @b", &[]).unwrap().0, "SELECT 1 -- @b");
+    }
+
+    #[test]
+    fn declared_locals() {
+        let sql = "DECLARE @local int = @a; SET @local += 1; SELECT @local, @a";
+        let (out, bound) = bind(sql, &["a"]).unwrap();
+        assert_eq!(
+            out,
+            "DECLARE @local int = @P1; SET @local += 1; SELECT @local, @P1"
+        );
+        assert_eq!(bound, 1);
+        let sql = "declare @x int, @y nvarchar(10), /* c */ @z int; SELECT @x + @y + @z";
+        assert_eq!(bind(sql, &[]).unwrap().0, sql);
+        // A comma after the declaration list binds as usual.
+        let sql = "DECLARE @x int; SELECT @x, @a";
+        assert_eq!(
+            bind(sql, &["a"]).unwrap().0,
+            "DECLARE @x int; SELECT @x, @P1"
+        );
+        assert!(matches!(
+            bind("DECLARE @x int; SELECT @x, @y", &[]),
+            Err(SqlServerError::MissingParameter(name)) if name == "y"
+        ));
+        // So does a statement following the list without `;`.
+        assert!(matches!(
+            bind("DECLARE @x int SELECT @x, @y", &[]),
+            Err(SqlServerError::MissingParameter(name)) if name == "y"
+        ));
+        let sql = "DECLARE @x int = (SELECT 1), @y int SET @y = @x";
+        assert_eq!(bind(sql, &[]).unwrap().0, sql);
+    }
+}
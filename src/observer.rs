@@ -0,0 +1,108 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use deadpool::managed::Object;
+
+use crate::{Manager, SqlServerError};
+
+/// Callbacks on pool events, e.g. to feed the counters and histograms of a metrics crate, see
+/// [`Manager::observer`].
+///
+/// All methods do nothing by default, implement the ones needed. They are called inline on the
+/// path of `pool.get()`, so should be cheap and must not block.
+///
+/// ```
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// #[derive(Default)]
+/// struct CreateCounter(AtomicU64);
+///
+/// impl deadpool_tiberius::PoolObserver for CreateCounter {
+///     fn on_create(&self, _latency: Duration) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let manager = deadpool_tiberius::Manager::new().observer(Arc::new(CreateCounter::default()));
+/// ```
+pub trait PoolObserver: Send + Sync {
+    /// A connection was created, connecting took `latency`.
+    fn on_create(&self, latency: Duration) {
+        let _ = latency;
+    }
+
+    /// Creating a connection failed with `error`.
+    fn on_create_error(&self, error: &SqlServerError) {
+        let _ = error;
+    }
+
+    /// A pooled connection was recycled, or discarded if not `success`.
+    fn on_recycle(&self, success: bool) {
+        let _ = success;
+    }
+
+    /// A connection was checked out with [`PoolExt::get_observed`], after waiting `wait`.
+    ///
+    /// [`PoolExt::get_observed`]: crate::PoolExt::get_observed
+    fn on_checkout(&self, wait: Duration) {
+        let _ = wait;
+    }
+
+    /// A connection checked out with [`PoolExt::get_observed`] was returned to the pool after
+    /// being held for `held`.
+    ///
+    /// [`PoolExt::get_observed`]: crate::PoolExt::get_observed
+    fn on_return(&self, held: Duration) {
+        let _ = held;
+    }
+}
+
+/// A pooled connection reporting its return to the [`PoolObserver`] when dropped, see
+/// [`PoolExt::get_observed`](crate::PoolExt::get_observed).
+pub struct ObservedConnection {
+    object: Object<Manager>,
+    observer: Option<Arc<dyn PoolObserver>>,
+    checked_out: Instant,
+}
+
+impl ObservedConnection {
+    pub(crate) fn new(
+        object: Object<Manager>,
+        observer: Option<Arc<dyn PoolObserver>>,
+        wait: Duration,
+    ) -> Self {
+        if let Some(observer) = &observer {
+            observer.on_checkout(wait);
+        }
+        Self {
+            object,
+            observer,
+            checked_out: Instant::now(),
+        }
+    }
+}
+
+impl Deref for ObservedConnection {
+    type Target = Object<Manager>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.object
+    }
+}
+
+impl DerefMut for ObservedConnection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.object
+    }
+}
+
+impl Drop for ObservedConnection {
+    fn drop(&mut self) {
+        if let Some(observer) = &self.observer {
+            observer.on_return(self.checked_out.elapsed());
+        }
+    }
+}
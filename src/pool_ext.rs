@@ -0,0 +1,345 @@
+use std::future::Future;
+use std::time::Duration;
+
+use deadpool::async_trait;
+use deadpool::managed::Object;
+use futures_util::future::BoxFuture;
+use tiberius::{FromSqlOwned, IntoRow, Row, ToSql};
+
+use crate::{
+    maintenance, taint, Client, ClientExt, Manager, ObservedConnection, Pool, SqlServerError,
+    SqlServerResult, TimeoutKind, Transaction,
+};
+
+/// How often a closed pool is checked for outstanding connections.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Convenience methods on a [`Pool`].
+#[async_trait]
+pub trait PoolExt {
+    /// Close the pool and wait until all checked out connections have been returned.
+    ///
+    /// New `get()` calls and the ones waiting fail with
+    /// [`PoolError::Closed`](deadpool::managed::PoolError::Closed), idle connections are closed
+    /// right away and checked out ones as they are returned. Calling it again, or on a pool
+    /// closed otherwise, just waits for the outstanding connections.
+    async fn graceful_shutdown(&self);
+
+    /// Like [`graceful_shutdown`](PoolExt::graceful_shutdown), but first runs `cleanup` on every
+    /// idle connection, e.g. to log or reset session state. Errors of `cleanup` are ignored,
+    /// connections checked out at the time are closed without it.
+    async fn graceful_shutdown_with(&self, cleanup: &str);
+
+    /// Like `get()`, but failing with a [`SqlServerError::Timeout`] of kind
+    /// [`TimeoutKind::Get`] if no connection is retrieved within `timeout`, e.g. to shed load
+    /// instead of queueing on a latency-sensitive path.
+    ///
+    /// Independent of the pool's timeouts, which still apply within `timeout`. On timeout the
+    /// pending `get()` is dropped, giving up its place in the queue, and a connection being
+    /// created meanwhile is discarded.
+    async fn get_timeout(&self, timeout: Duration) -> SqlServerResult<Object<Manager>>;
+
+    /// Like `get()`, but runs `f` on the connection before handing it out, e.g. to scope the
+    /// session to the current tenant. Unlike the `post_create` and `pre_recycle` hooks, this runs
+    /// on every checkout, including of freshly created connections.
+    ///
+    /// If `f` fails, the connection is closed rather than returned to the pool, since its session
+    /// state may be partially applied.
+    /// ```no_run
+    /// # use deadpool_tiberius::PoolExt;
+    /// # async fn f(pool: &deadpool_tiberius::Pool) -> deadpool_tiberius::SqlServerResult<()> {
+    /// let tenant_id = 42;
+    /// let mut conn = pool
+    ///     .get_with(move |client| {
+    ///         Box::pin(async move {
+    ///             client
+    ///                 .execute("EXEC sp_set_session_context N'tenant_id', @P1", &[&tenant_id])
+    ///                 .await?;
+    ///             Ok(())
+    ///         })
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn get_with<F>(&self, f: F) -> SqlServerResult<Object<Manager>>
+    where
+        F: for<'a> FnOnce(&'a mut Client) -> BoxFuture<'a, SqlServerResult<()>> + Send;
+
+    /// Check out a connection and start a transaction on it with `BEGIN TRANSACTION`, rolled
+    /// back unless committed, see [`Transaction`].
+    /// ```no_run
+    /// # use deadpool_tiberius::PoolExt;
+    /// # async fn f(pool: &deadpool_tiberius::Pool) -> deadpool_tiberius::SqlServerResult<()> {
+    /// let mut tx = pool.begin().await?;
+    /// tx.execute("UPDATE accounts SET balance = balance - 10 WHERE id = 1", &[]).await?;
+    /// // An error here drops `tx`, rolling back the first update.
+    /// tx.execute("UPDATE accounts SET balance = balance + 10 WHERE id = 2", &[]).await?;
+    /// tx.commit().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn begin(&self) -> SqlServerResult<Transaction>;
+
+    /// Check out a connection switched to the database `name` with
+    /// [`ClientExt::use_database`], e.g. to serve several databases of a server from one pool.
+    ///
+    /// Recycling switches the connection back to the pool's database before it is handed out
+    /// again, even within a [`recycle_min_idle`] window, unless disabled with
+    /// [`reset_database_on_recycle`] or replaced by a [`recycle_check`], so the switch doesn't
+    /// leak to the next borrower.
+    ///
+    /// [`recycle_min_idle`]: crate::Manager::recycle_min_idle
+    /// [`reset_database_on_recycle`]: crate::Manager::reset_database_on_recycle
+    /// [`recycle_check`]: crate::Manager::recycle_check
+    async fn get_for_database(&self, name: &str) -> SqlServerResult<Object<Manager>>;
+
+    /// Check out a connection, reporting the checkout and, once the returned guard is dropped,
+    /// the return to the [`observer`](crate::Manager::observer), see [`ObservedConnection`].
+    /// Connections checked out with `get()` aren't reported.
+    async fn get_observed(&self) -> SqlServerResult<ObservedConnection>;
+}
+
+#[async_trait]
+impl PoolExt for Pool {
+    async fn graceful_shutdown(&self) {
+        self.close();
+        while self.status().size > 0 {
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn graceful_shutdown_with(&self, cleanup: &str) {
+        for mut conn in maintenance::take_idle(self).await {
+            if let Ok(stream) = conn.simple_query(cleanup).await {
+                let _ = stream.into_results().await;
+            }
+        }
+        self.graceful_shutdown().await;
+    }
+
+    async fn get_timeout(&self, timeout: Duration) -> SqlServerResult<Object<Manager>> {
+        match tokio::time::timeout(timeout, self.get()).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(SqlServerError::Timeout {
+                kind: TimeoutKind::Get,
+            }),
+        }
+    }
+
+    async fn get_with<F>(&self, f: F) -> SqlServerResult<Object<Manager>>
+    where
+        F: for<'a> FnOnce(&'a mut Client) -> BoxFuture<'a, SqlServerResult<()>> + Send,
+    {
+        let mut client = self.get().await?;
+        match f(&mut client).await {
+            Ok(()) => Ok(client),
+            Err(e) => {
+                let _ = taint::discard(client);
+                Err(e)
+            }
+        }
+    }
+
+    async fn begin(&self) -> SqlServerResult<Transaction> {
+        Transaction::begin(self.get().await?).await
+    }
+
+    async fn get_for_database(&self, name: &str) -> SqlServerResult<Object<Manager>> {
+        let mut client = self.get().await?;
+        client.use_database(name).await?;
+        Ok(client)
+    }
+
+    async fn get_observed(&self) -> SqlServerResult<ObservedConnection> {
+        let started = std::time::Instant::now();
+        let client = self.get().await?;
+        let observer = self.manager().observer.clone();
+        Ok(ObservedConnection::new(client, observer, started.elapsed()))
+    }
+}
+
+/// One-shot queries on a [`Pool`], checking out a connection for the query and returning it
+/// right after. Queries are prefixed with the [`query_comment`](crate::Manager::query_comment)
+/// and bounded by the [`query_timeout`](crate::Manager::query_timeout).
+///
+/// `params` are bound as parameters of an RPC request, referenced in `sql` as `@P1`, `@P2`, ...
+/// in order. They are never spliced into the sql text, so values from untrusted input can't
+/// inject sql, prefer them over formatting values into `sql`. See
+/// [`ClientExt::query_named`](crate::ClientExt::query_named) for named placeholders.
+/// ```no_run
+/// # use deadpool_tiberius::PoolQueryExt;
+/// # async fn f(pool: &deadpool_tiberius::Pool) -> deadpool_tiberius::SqlServerResult<()> {
+/// let rows = pool
+///     .fetch_all("SELECT name FROM users WHERE active = @P1", &[&true])
+///     .await?;
+/// let deleted = pool.execute("DELETE FROM sessions WHERE expired = 1", &[]).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[async_trait]
+pub trait PoolQueryExt {
+    /// All rows of the first result set of `sql`.
+    async fn fetch_all(&self, sql: &str, params: &[&dyn ToSql]) -> SqlServerResult<Vec<Row>>;
+
+    /// The first row of `sql`, `None` if it returns no rows.
+    async fn fetch_one(&self, sql: &str, params: &[&dyn ToSql]) -> SqlServerResult<Option<Row>>;
+
+    /// Execute `sql`, returning the total number of affected rows, summed over all statements
+    /// of a batch.
+    async fn execute(&self, sql: &str, params: &[&dyn ToSql]) -> SqlServerResult<u64>;
+
+    /// The first column of the first row of `sql`, e.g. of a `SELECT COUNT(*)`, `None` if it
+    /// returns no rows or `NULL`.
+    ///
+    /// Fails with [`SqlServerError::ScalarConversion`] if the sql type of the value doesn't map
+    /// to `T`, e.g. a `bigint` from `COUNT_BIG(*)` read as `i32`.
+    /// ```no_run
+    /// # use deadpool_tiberius::PoolQueryExt;
+    /// # async fn f(pool: &deadpool_tiberius::Pool) -> deadpool_tiberius::SqlServerResult<()> {
+    /// let users: Option<i32> = pool.fetch_scalar("SELECT COUNT(*) FROM users", &[]).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn fetch_scalar<T>(&self, sql: &str, params: &[&dyn ToSql]) -> SqlServerResult<Option<T>>
+    where
+        T: FromSqlOwned + Send;
+}
+
+#[async_trait]
+impl PoolQueryExt for Pool {
+    async fn fetch_all(&self, sql: &str, params: &[&dyn ToSql]) -> SqlServerResult<Vec<Row>> {
+        let mut client = self.get().await?;
+        let sql = self.manager().commented(sql);
+        let result = bounded(self, async {
+            Ok(client.query(sql, params).await?.into_first_result().await?)
+        })
+        .await;
+        discard_timed_out(client, &result);
+        result
+    }
+
+    async fn fetch_one(&self, sql: &str, params: &[&dyn ToSql]) -> SqlServerResult<Option<Row>> {
+        let mut client = self.get().await?;
+        let sql = self.manager().commented(sql);
+        let result = bounded(self, async {
+            Ok(client.query(sql, params).await?.into_row().await?)
+        })
+        .await;
+        discard_timed_out(client, &result);
+        result
+    }
+
+    async fn execute(&self, sql: &str, params: &[&dyn ToSql]) -> SqlServerResult<u64> {
+        let mut client = self.get().await?;
+        let sql = self.manager().commented(sql);
+        let result = bounded(self, async {
+            Ok(client.execute(sql, params).await?.total())
+        })
+        .await;
+        discard_timed_out(client, &result);
+        result
+    }
+
+    async fn fetch_scalar<T>(&self, sql: &str, params: &[&dyn ToSql]) -> SqlServerResult<Option<T>>
+    where
+        T: FromSqlOwned + Send,
+    {
+        let Some(row) = self.fetch_one(sql, params).await? else {
+            return Ok(None);
+        };
+        match row.into_iter().next() {
+            Some(value) => {
+                T::from_sql_owned(value).map_err(|source| SqlServerError::ScalarConversion {
+                    target: std::any::type_name::<T>(),
+                    source,
+                })
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Run `query`, bounded by the [`query_timeout`](crate::Manager::query_timeout) of `pool`.
+async fn bounded<T>(
+    pool: &Pool,
+    query: impl Future<Output = SqlServerResult<T>>,
+) -> SqlServerResult<T> {
+    match pool.manager().query_timeout {
+        Some(timeout) => {
+            tokio::time::timeout(timeout, query)
+                .await
+                .unwrap_or(Err(SqlServerError::Timeout {
+                    kind: TimeoutKind::Query,
+                }))
+        }
+        None => query.await,
+    }
+}
+
+/// Close `client` instead of returning it to the pool if its query timed out, as the response
+/// may still be pending on the connection.
+fn discard_timed_out<T>(client: Object<Manager>, result: &SqlServerResult<T>) {
+    if let Err(SqlServerError::Timeout {
+        kind: TimeoutKind::Query,
+    }) = result
+    {
+        let _ = taint::discard(client);
+    }
+}
+
+/// Bulk loads through a [`Pool`], the fastest way of inserting many rows.
+#[async_trait]
+pub trait PoolBulkExt {
+    /// Insert `rows` into `table` with a TDS bulk load on a connection checked out for the
+    /// duration, returning the number of rows inserted.
+    ///
+    /// The rows are streamed to the server as `rows` is iterated. Values are converted to the
+    /// types of the table's columns in order, every row must hold a value for each column. A
+    /// connection failing mid-load is discarded instead of returned to the pool.
+    ///
+    /// This takes the rows rather than handing out the [`tiberius::BulkLoadRequest`], which
+    /// borrows its connection. To drive the request yourself, check out a connection with
+    /// `pool.get()` and call [`Client::bulk_insert`](tiberius::Client::bulk_insert) on it.
+    /// ```no_run
+    /// # use deadpool_tiberius::{PoolBulkExt, PoolQueryExt};
+    /// # async fn f(pool: &deadpool_tiberius::Pool) -> deadpool_tiberius::SqlServerResult<()> {
+    /// pool.execute("CREATE TABLE ##events (id INT NOT NULL, name NVARCHAR(50))", &[])
+    ///     .await?;
+    ///
+    /// let rows = (0..5000).map(|id| (id, format!("event {}", id)));
+    /// let inserted = pool.bulk_insert("##events", rows).await?;
+    /// assert_eq!(inserted, 5000);
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn bulk_insert<I>(&self, table: &str, rows: I) -> SqlServerResult<u64>
+    where
+        I: IntoIterator + Send,
+        I::IntoIter: Send,
+        I::Item: IntoRow<'static> + Send;
+}
+
+#[async_trait]
+impl PoolBulkExt for Pool {
+    async fn bulk_insert<I>(&self, table: &str, rows: I) -> SqlServerResult<u64>
+    where
+        I: IntoIterator + Send,
+        I::IntoIter: Send,
+        I::Item: IntoRow<'static> + Send,
+    {
+        let mut client = self.get().await?;
+        let result = async {
+            let mut request = client.bulk_insert(table).await?;
+            for row in rows {
+                request.send(row.into_row()).await?;
+            }
+            Ok(request.finalize().await?.total())
+        }
+        .await;
+        if result.is_err() {
+            let _ = taint::discard(client);
+        }
+        result
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use deadpool::managed::{Object, PoolError};
+
+use crate::{Manager, Pool, SqlServerError, SqlServerResult};
+
+/// A pair of pools splitting reads from writes, e.g. a readable secondary replica and the
+/// primary, see [`Manager::split`].
+///
+/// Reads can be spread over several replicas, see [`round_robin`](ReadWritePool::round_robin).
+pub struct ReadWritePool {
+    write: Pool,
+    reads: Vec<Pool>,
+    next_read: AtomicUsize,
+}
+
+impl ReadWritePool {
+    /// Route reads to `read` and writes to `write`.
+    pub fn new(read: Pool, write: Pool) -> Self {
+        Self::round_robin(vec![read], write)
+    }
+
+    /// Route reads to `reads` in turn and writes to `write`. Reads go to `write` as well if
+    /// `reads` is empty.
+    pub fn round_robin(reads: Vec<Pool>, write: Pool) -> Self {
+        Self {
+            write,
+            reads,
+            next_read: AtomicUsize::new(0),
+        }
+    }
+
+    /// Retrieve a connection for reading, from the next read pool in turn.
+    pub async fn read(&self) -> Result<Object<Manager>, PoolError<SqlServerError>> {
+        self.read_pool().get().await
+    }
+
+    /// Retrieve a connection for writing.
+    pub async fn write(&self) -> Result<Object<Manager>, PoolError<SqlServerError>> {
+        self.write.get().await
+    }
+
+    /// The next read pool in turn.
+    pub fn read_pool(&self) -> &Pool {
+        if self.reads.is_empty() {
+            return &self.write;
+        }
+        let idx = self.next_read.fetch_add(1, Ordering::Relaxed);
+        &self.reads[idx % self.reads.len()]
+    }
+
+    /// The write pool.
+    pub fn write_pool(&self) -> &Pool {
+        &self.write
+    }
+}
+
+impl Manager {
+    /// Build a [`ReadWritePool`] from a manager for reads, e.g. configured with
+    /// [`application_intent_readonly`](Manager::application_intent_readonly), and one for writes.
+    pub fn split(read: Manager, write: Manager) -> SqlServerResult<ReadWritePool> {
+        Ok(ReadWritePool::new(
+            read.create_pool()?,
+            write.create_pool()?,
+        ))
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::Pool;
+
+/// Cumulative statistics over the lifetime of a pool, see [`lifetime_stats`].
+///
+/// Unlike [`deadpool::Status`] which is a point-in-time view, these counters only grow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LifetimeStats {
+    /// Total number of connections created.
+    pub connections_created: u64,
+    /// Total number of connections discarded because recycling failed.
+    pub recycle_failures: u64,
+    /// Total number of connections handed out, either freshly created or recycled.
+    pub acquisitions: u64,
+    /// Average time spent creating a connection.
+    pub average_create_latency: Duration,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct StatsCounters {
+    connections_created: AtomicU64,
+    recycle_failures: AtomicU64,
+    acquisitions: AtomicU64,
+    create_latency_nanos: AtomicU64,
+}
+
+impl StatsCounters {
+    pub(crate) fn created(&self, latency: Duration) {
+        self.connections_created.fetch_add(1, Ordering::Relaxed);
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        self.create_latency_nanos
+            .fetch_add(latency.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn recycled(&self, success: bool) {
+        if success {
+            self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.recycle_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn snapshot(&self) -> LifetimeStats {
+        let connections_created = self.connections_created.load(Ordering::Relaxed);
+        let latency = self.create_latency_nanos.load(Ordering::Relaxed);
+        LifetimeStats {
+            connections_created,
+            recycle_failures: self.recycle_failures.load(Ordering::Relaxed),
+            acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            average_create_latency: Duration::from_nanos(
+                latency.checked_div(connections_created).unwrap_or(0),
+            ),
+        }
+    }
+}
+
+/// Cumulative statistics of `pool` since it was created.
+pub fn lifetime_stats(pool: &Pool) -> LifetimeStats {
+    pool.manager().stats.snapshot()
+}
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use deadpool::managed::{Object, PoolError};
+
+use crate::{maintenance, Manager, Pool, SqlServerError, SqlServerResult};
+
+/// How often a replaced pool is checked for outstanding connections.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A [`Pool`] which can be replaced as a whole at runtime, e.g. on a config reload.
+///
+/// [`get`](SwappablePool::get) always uses the current pool. [`reload`](SwappablePool::reload)
+/// builds a new pool and swaps it in, the replaced pool keeps serving the connections already
+/// checked out and is closed once they all returned.
+pub struct SwappablePool {
+    current: ArcSwap<Pool>,
+}
+
+impl SwappablePool {
+    /// Wrap `pool` as the current pool.
+    pub fn new(pool: Pool) -> Self {
+        Self {
+            current: ArcSwap::from_pointee(pool),
+        }
+    }
+
+    /// The current pool.
+    pub fn load(&self) -> Arc<Pool> {
+        self.current.load_full()
+    }
+
+    /// Retrieve a connection from the current pool.
+    pub async fn get(&self) -> Result<Object<Manager>, PoolError<SqlServerError>> {
+        self.load().get().await
+    }
+
+    /// Build a pool from `manager` and swap it in, see [`SwappablePool`].
+    ///
+    /// The replaced pool is drained and closed in a spawned tokio task, so this must be called
+    /// within a tokio runtime.
+    pub async fn reload(&self, manager: Manager) -> SqlServerResult<()> {
+        self.reload_prewarmed(manager, 0).await
+    }
+
+    /// Like [`reload`](SwappablePool::reload), but opens `connections` connections on the new pool
+    /// before swapping it in, so the first requests after the swap don't pay for connecting.
+    /// Fails without swapping if any of them can't be opened.
+    pub async fn reload_prewarmed(
+        &self,
+        manager: Manager,
+        connections: usize,
+    ) -> SqlServerResult<()> {
+        let pool = manager.create_pool()?;
+        maintenance::prefill(&pool, connections).await?;
+
+        let old = self.current.swap(Arc::new(pool));
+        tokio::spawn(async move {
+            loop {
+                let status = old.status();
+                if status.available == status.size && status.waiting == 0 {
+                    break;
+                }
+                tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+            }
+            old.close();
+        });
+        Ok(())
+    }
+}
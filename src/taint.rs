@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use deadpool::managed::Object;
+
+use crate::{Client, Manager};
+
+/// State a checked-out connection was left in which recycling must not skip, ordered by
+/// severity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Taint {
+    /// Switched to another database, the full recycle batch must run to switch it back.
+    Database,
+    /// Dropped with an open transaction, the connection must be discarded.
+    Transaction,
+}
+
+/// Taints of pooled connections, keyed by their creation time, which tells the connections of
+/// a pool apart. A collision only costs a needless recycle batch or discard.
+///
+/// A taint is only kept while recycling may skip the probe which catches it otherwise, see
+/// [`Taints::mark`], and until the connection is recycled or discarded by this crate.
+#[derive(Default)]
+pub(crate) struct Taints(Mutex<HashMap<Instant, Marked>>);
+
+/// A taint and until when it matters, `None` for as long as the connection lives.
+type Marked = (Taint, Option<Instant>);
+
+impl Taints {
+    /// Taint the connection created at `created` of the pool of `manager`, keeping the more
+    /// severe taint if already tainted.
+    ///
+    /// Without a [`recycle_check`](Manager::recycle_check), recycling only skips the probe,
+    /// which catches both taints, within [`recycle_min_idle`](Manager::recycle_min_idle) of
+    /// checking the connection out, the taint expires then. A recycle check replaces the probe,
+    /// an open transaction is then kept until the connection is recycled.
+    pub(crate) fn mark(manager: &Manager, created: Instant, taint: Taint) {
+        let expires = match (&manager.recycle_check, manager.recycle_min_idle) {
+            (Some(_), _) if taint == Taint::Transaction => None,
+            (_, Some(min_idle)) => Some(Instant::now() + min_idle),
+            _ => return,
+        };
+        let mut taints = manager.taints.0.lock().unwrap();
+        let now = Instant::now();
+        taints.retain(|_, (_, until)| until.is_none_or(|until| until > now));
+        let entry = taints.entry(created).or_insert((taint, expires));
+        entry.0 = entry.0.max(taint);
+        entry.1 = entry.1.zip(expires).map(|(a, b)| a.max(b));
+    }
+
+    /// Taint the pooled connection `conn`, does nothing once detached from its pool.
+    pub(crate) fn mark_pooled(conn: &Object<Manager>, taint: Taint) {
+        if let Some(pool) = Object::pool(conn) {
+            Self::mark(pool.manager(), Object::metrics(conn).created, taint);
+        }
+    }
+
+    /// Remove and return the taint of the connection created at `created`.
+    pub(crate) fn take(&self, created: Instant) -> Option<Taint> {
+        self.0
+            .lock()
+            .unwrap()
+            .remove(&created)
+            .map(|(taint, _)| taint)
+    }
+}
+
+/// Take `conn` out of its pool to close it, forgetting its taint.
+pub(crate) fn discard(conn: Object<Manager>) -> Client {
+    if let Some(pool) = Object::pool(&conn) {
+        pool.manager().taints.take(Object::metrics(&conn).created);
+    }
+    Object::take(conn)
+}
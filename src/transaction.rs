@@ -0,0 +1,181 @@
+use std::ops::{Deref, DerefMut};
+use std::time::Instant;
+
+use deadpool::managed::{self, Object};
+
+use crate::taint::{self, Taint, Taints};
+use crate::{Client, Manager, SqlServerResult};
+
+/// An open transaction on a [`Client`], see [`ClientExt::begin_transaction`].
+///
+/// Must be finished explicitly with [`commit`] or [`rollback`], as there is no async drop to
+/// do so on scope exit. Dropping an unfinished guard begun on a pooled connection marks the
+/// connection, which the pool discards instead of handing it out again, so the transaction
+/// never leaks to the next borrower. A guard begun on a bare [`Client`], e.g. the target of a
+/// pooled connection's deref, relies on the open transaction check of the recycle probe
+/// instead, which a [`recycle_check`] or [`recycle_min_idle`] can skip.
+///
+/// [`ClientExt::begin_transaction`]: crate::ClientExt::begin_transaction
+/// [`commit`]: TransactionGuard::commit
+/// [`rollback`]: TransactionGuard::rollback
+/// [`recycle_check`]: crate::Manager::recycle_check
+/// [`recycle_min_idle`]: crate::Manager::recycle_min_idle
+#[must_use = "a transaction must be finished with `commit` or `rollback`"]
+pub struct TransactionGuard<'a> {
+    client: &'a mut Client,
+    /// The connection's pool and creation time, to mark it if dropped unfinished.
+    pooled: Option<(managed::Pool<Manager>, Instant)>,
+    finished: bool,
+}
+
+impl<'a> TransactionGuard<'a> {
+    pub(crate) async fn begin(client: &'a mut Client) -> SqlServerResult<TransactionGuard<'a>> {
+        run(client, "BEGIN TRANSACTION").await?;
+        Ok(Self {
+            client,
+            pooled: None,
+            finished: false,
+        })
+    }
+
+    pub(crate) async fn begin_pooled(
+        conn: &'a mut Object<Manager>,
+    ) -> SqlServerResult<TransactionGuard<'a>> {
+        let pooled = Object::pool(conn).map(|pool| (pool, Object::metrics(conn).created));
+        let mut guard = Self::begin(conn).await?;
+        guard.pooled = pooled;
+        Ok(guard)
+    }
+
+    /// Commit the transaction.
+    pub async fn commit(mut self) -> SqlServerResult<()> {
+        self.finish("COMMIT TRANSACTION").await
+    }
+
+    /// Roll back the transaction.
+    pub async fn rollback(mut self) -> SqlServerResult<()> {
+        self.finish("ROLLBACK TRANSACTION").await
+    }
+
+    async fn finish(&mut self, sql: &str) -> SqlServerResult<()> {
+        self.finished = true;
+        run(self.client, sql).await
+    }
+}
+
+impl Deref for TransactionGuard<'_> {
+    type Target = Client;
+
+    fn deref(&self) -> &Self::Target {
+        self.client
+    }
+}
+
+impl DerefMut for TransactionGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.client
+    }
+}
+
+impl Drop for TransactionGuard<'_> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            "TransactionGuard dropped without commit or rollback, \
+             discarding the connection on recycle"
+        );
+        if let Some((pool, created)) = &self.pooled {
+            Taints::mark(pool.manager(), *created, Taint::Transaction);
+        }
+    }
+}
+
+/// An open transaction on a connection checked out of the pool, see
+/// [`PoolExt::begin`](crate::PoolExt::begin).
+///
+/// Unlike [`TransactionGuard`], it owns the connection, which returns to the pool once the
+/// transaction is finished with [`commit`] or [`rollback`]. Dropping an unfinished transaction,
+/// e.g. on an early `?` return, rolls it back on a spawned task, as there is no async drop, and
+/// returns the connection once done. Outside of a tokio runtime, or if the rollback fails, the
+/// connection is closed instead, so an open transaction never reaches the next borrower.
+///
+/// [`commit`]: Transaction::commit
+/// [`rollback`]: Transaction::rollback
+#[must_use = "a transaction is rolled back when dropped without `commit`"]
+pub struct Transaction {
+    /// `None` once finished.
+    conn: Option<Object<Manager>>,
+}
+
+impl Transaction {
+    pub(crate) async fn begin(mut conn: Object<Manager>) -> SqlServerResult<Self> {
+        run(&mut conn, "BEGIN TRANSACTION").await?;
+        Ok(Self { conn: Some(conn) })
+    }
+
+    /// Commit the transaction, returning the connection to the pool.
+    ///
+    /// If committing fails, the connection is closed, as the state of the transaction is
+    /// unknown.
+    pub async fn commit(mut self) -> SqlServerResult<()> {
+        self.finish("COMMIT TRANSACTION").await
+    }
+
+    /// Roll back the transaction, returning the connection to the pool.
+    pub async fn rollback(mut self) -> SqlServerResult<()> {
+        self.finish("ROLLBACK TRANSACTION").await
+    }
+
+    async fn finish(&mut self, sql: &str) -> SqlServerResult<()> {
+        let mut conn = self.conn.take().expect("transaction is unfinished");
+        let result = run(&mut conn, sql).await;
+        if result.is_err() {
+            let _ = taint::discard(conn);
+        }
+        result
+    }
+}
+
+impl Deref for Transaction {
+    type Target = Client;
+
+    fn deref(&self) -> &Self::Target {
+        self.conn.as_ref().expect("transaction is unfinished")
+    }
+}
+
+impl DerefMut for Transaction {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.conn.as_mut().expect("transaction is unfinished")
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        let Some(mut conn) = self.conn.take() else {
+            return;
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!("transaction dropped without commit, rolling back");
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    if run(&mut conn, "ROLLBACK TRANSACTION").await.is_err() {
+                        let _ = taint::discard(conn);
+                    }
+                });
+            }
+            Err(_) => {
+                let _ = taint::discard(conn);
+            }
+        }
+    }
+}
+
+async fn run(client: &mut Client, sql: &str) -> SqlServerResult<()> {
+    client.simple_query(sql).await?.into_results().await?;
+    Ok(())
+}